@@ -9,10 +9,32 @@ import sys
 import json
 from os import path
 
+def load_metadata(metapath):
+    """Read an existing .meme file. They're JSON5, which needs the json5
+    package unless the file happens to be plain JSON."""
+    with open(metapath) as infile:
+        text = infile.read()
+    try:
+        return json.loads(text)
+    except json.JSONDecodeError:
+        pass
+    try:
+        import json5
+    except ImportError:
+        sys.exit(f"{metapath} is JSON5, not plain JSON. Install the json5 package "
+                 "(pip install json5) to edit it, or convert it to JSON.")
+    try:
+        return json5.loads(text)
+    except ValueError as e:
+        sys.exit(f"{metapath} is not valid JSON5: {e}")
+
 window = tk.Tk()
 
 filepath = sys.argv[1]
 print("Opening file:", filepath)
+metapath = path.splitext(filepath)[0] + ".meme"
+# keep aliases and anything else already in the metadata
+mememeta = load_metadata(metapath) if path.exists(metapath) else {}
 with Image.open(filepath) as image:
     newheight = 600
     ratio = newheight / image.height
@@ -63,14 +85,16 @@ with Image.open(filepath) as image:
     imagelabel.bind("<Button-1>", imageleftclick)
 
     def save_and_exit(event):
-        mememeta = {"inserts": memelabels}
+        newfilepath = metapath
+        # redrawn inserts keep their style, if they had one
+        oldinserts = mememeta.get("inserts", {})
+        mememeta["inserts"] = {name: {**oldinserts.get(name, {}), **ins} for name, ins in memelabels.items()}
         mememetajson = json.dumps(mememeta, indent = 2)
-        newfilepath = path.splitext(filepath)[0] + ".meme"
         print(mememetajson)
         print(newfilepath)
         exists = ""
         if path.exists(newfilepath):
-            exists = "\n\nWARNING: FILE ALREADY EXISTS! WILL OVERWRITE"
+            exists = "\n\nWARNING: FILE ALREADY EXISTS! WILL OVERWRITE INSERTS AND SAVE AS PLAIN JSON (COMMENTS ARE LOST)"
         answer = messagebox.askokcancel("Save Meme Meta", f"Do you want to save as\n{newfilepath}\nPlease confirm.{exists}")
         if answer:
             print("Saving!")
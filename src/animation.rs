@@ -0,0 +1,185 @@
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::imageops::{resize, FilterType};
+use image::{AnimationDecoder, Frame, ImageFormat, RgbaImage};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::io::Cursor;
+use std::path::Path;
+
+use crate::error::MdlError;
+
+/// Most pixels, over all frames, an animation may decode to.
+pub const MAX_ANIMATION_PIXELS: u64 = 50_000_000;
+
+/// How many times to shrink an animation which doesn't fit its size budget
+/// before giving up.
+const MAX_SHRINK_ATTEMPTS: usize = 4;
+
+/// Frames to draw on, from 0, as `[first, last]`. Frames outside it are
+/// left as they are.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "[usize; 2]")]
+pub struct FrameRange {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl FrameRange {
+    pub fn contains(&self, frame: usize) -> bool {
+        (self.first..=self.last).contains(&frame)
+    }
+}
+
+impl TryFrom<[usize; 2]> for FrameRange {
+    type Error = String;
+
+    fn try_from([first, last]: [usize; 2]) -> Result<Self, Self::Error> {
+        if first > last {
+            return Err(format!(
+                "frame range [{}, {}] ends before it starts",
+                first, last
+            ));
+        }
+        Ok(FrameRange { first, last })
+    }
+}
+
+/// Read a picture file into its frames. See `decode`.
+pub fn open(path: &Path) -> Result<Vec<Frame>, String> {
+    let data = std::fs::read(path).map_err(|e| e.to_string())?;
+    let format = ImageFormat::from_path(path)
+        .or_else(|_| image::guess_format(&data))
+        .map_err(|e| e.to_string())?;
+    decode(&data, format)
+}
+
+/// Decode a picture into frames, each the full size of the picture.
+/// Animated GIFs and PNGs have one per frame of animation, anything else
+/// has just one.
+pub fn decode(data: &[u8], format: ImageFormat) -> Result<Vec<Frame>, String> {
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(Cursor::new(data))
+            .map_err(|e| e.to_string())?
+            .into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(data)).map_err(|e| e.to_string())?;
+            if !decoder.is_apng() {
+                return still(data, format);
+            }
+            decoder.apng().into_frames()
+        }
+        _ => return still(data, format),
+    };
+
+    let mut decoded: Vec<Frame> = Vec::new();
+    let mut pixels = 0;
+    for frame in frames {
+        let frame = frame.map_err(|e| e.to_string())?;
+        pixels += frame.buffer().width() as u64 * frame.buffer().height() as u64;
+        if pixels > MAX_ANIMATION_PIXELS {
+            return Err(format!(
+                "the animation is more than {} megapixels over all its frames.",
+                MAX_ANIMATION_PIXELS / 1_000_000
+            ));
+        }
+        decoded.push(frame);
+    }
+    if decoded.is_empty() {
+        return still(data, format);
+    }
+    Ok(decoded)
+}
+
+fn still(data: &[u8], format: ImageFormat) -> Result<Vec<Frame>, String> {
+    let picture = image::load_from_memory_with_format(data, format).map_err(|e| e.to_string())?;
+    Ok(vec![Frame::new(picture.into_rgba8())])
+}
+
+/// Encode frames as a looping GIF of at most `budget` bytes, shrinking them
+/// until it fits.
+pub fn encode_gif(frames: &[Frame], budget: usize) -> Result<Vec<u8>, MdlError> {
+    let mut scale = 1.0;
+    let mut size = 0;
+    for _ in 0..MAX_SHRINK_ATTEMPTS {
+        let gif = encode_gif_scaled(frames, scale)?;
+        if gif.len() <= budget {
+            return Ok(gif);
+        }
+        size = gif.len();
+        // the file grows about with the area, aim a little under
+        scale *= (budget as f64 / size as f64).sqrt() * 0.9;
+    }
+    Err(MdlError::RenderError(format!(
+        "The animation is too big to upload: still {:.1} MB after shrinking it to fit in {:.1} MB.",
+        size as f64 / (1024.0 * 1024.0),
+        budget as f64 / (1024.0 * 1024.0)
+    )))
+}
+
+fn encode_gif_scaled(frames: &[Frame], scale: f64) -> Result<Vec<u8>, MdlError> {
+    let mut gif = Vec::new();
+    {
+        // speed 10 of 30 is much faster than the default, and looks the same
+        let mut encoder = GifEncoder::new_with_speed(&mut gif, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for frame in frames {
+            let buffer = if scale < 1.0 {
+                scaled(frame.buffer(), scale)
+            } else {
+                frame.buffer().clone()
+            };
+            encoder.encode_frame(Frame::from_parts(buffer, 0, 0, frame.delay()))?;
+        }
+    }
+    Ok(gif)
+}
+
+/// An image resized by `scale`, at least 1 pixel each way.
+pub fn scaled(image: &RgbaImage, scale: f64) -> RgbaImage {
+    let width = ((image.width() as f64 * scale).round() as u32).max(1);
+    let height = ((image.height() as f64 * scale).round() as u32).max(1);
+    resize(image, width, height, FilterType::Triangle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_ranges_include_both_ends() {
+        let range = FrameRange::try_from([3, 5]).unwrap();
+        assert!(!range.contains(2));
+        assert!(range.contains(3));
+        assert!(range.contains(5));
+        assert!(!range.contains(6));
+
+        let single = FrameRange::try_from([0, 0]).unwrap();
+        assert!(single.contains(0));
+        assert!(!single.contains(1));
+    }
+
+    #[test]
+    fn inverted_frame_ranges_are_rejected() {
+        assert_eq!(
+            FrameRange::try_from([5, 3]),
+            Err("frame range [5, 3] ends before it starts".to_string())
+        );
+        assert!(FrameRange::try_from([1, 0]).is_err());
+    }
+
+    #[test]
+    fn frame_ranges_deserialize_from_pairs() {
+        let range: FrameRange = json5::from_str("[10, 30]").unwrap();
+        assert_eq!(
+            range,
+            FrameRange {
+                first: 10,
+                last: 30
+            }
+        );
+        assert!(json5::from_str::<FrameRange>("[30, 10]").is_err());
+        assert!(json5::from_str::<FrameRange>("[1]").is_err());
+        assert!(json5::from_str::<FrameRange>("\"1-3\"").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use image::{Frame, ImageFormat};
+use std::io::Cursor;
+
+use crate::animation;
+use crate::error::MdlError;
+
+/// Largest attachment we'll read, the same as Discord's upload limit.
+pub const MAX_ATTACHMENT_BYTES: usize = 8 * 1024 * 1024;
+
+/// Most pixels an attachment may decode to, so a small file can't unpack
+/// into gigabytes. Enough for a 12 megapixel phone photo.
+pub const MAX_ATTACHMENT_PIXELS: u64 = 16_000_000;
+
+/// Formats attachments may be in, by what the file starts with rather than
+/// its name.
+const ATTACHMENT_FORMATS: [(ImageFormat, &str); 5] = [
+    (ImageFormat::Png, "PNG"),
+    (ImageFormat::Jpeg, "JPEG"),
+    (ImageFormat::Gif, "GIF"),
+    (ImageFormat::WebP, "WebP"),
+    (ImageFormat::Bmp, "BMP"),
+];
+
+/// Decode `attachment:index` of `attachments`, checking its size and format
+/// before decoding it. Animations give their first frame.
+pub fn load(attachments: &[Vec<u8>], index: usize) -> Result<image::RgbaImage, MdlError> {
+    let (data, format) = check(attachments, index)?;
+    image::load_from_memory_with_format(data, format)
+        .map(|picture| picture.into_rgba8())
+        .map_err(|e| MdlError::BadAttachment {
+            index,
+            problem: e.to_string(),
+        })
+}
+
+/// Like `load`, but gives every frame of an animation.
+pub fn load_frames(attachments: &[Vec<u8>], index: usize) -> Result<Vec<Frame>, MdlError> {
+    let (data, format) = check(attachments, index)?;
+    animation::decode(data, format).map_err(|problem| MdlError::BadAttachment { index, problem })
+}
+
+/// Find an attachment, and check it's a picture we're willing to decode.
+fn check(attachments: &[Vec<u8>], index: usize) -> Result<(&[u8], ImageFormat), MdlError> {
+    let data = attachments.get(index).ok_or(MdlError::MissingAttachment {
+        index,
+        count: attachments.len(),
+    })?;
+    let bad = |problem: String| MdlError::BadAttachment { index, problem };
+
+    check_size(data.len() as u64).map_err(bad)?;
+    let format = image::guess_format(data)
+        .ok()
+        .filter(|format| ATTACHMENT_FORMATS.iter().any(|(f, _)| f == format))
+        .ok_or_else(|| {
+            bad(format!(
+                "it is not a picture, or not in a format we read ({}).",
+                format_names()
+            ))
+        })?;
+    let (width, height) = image::io::Reader::with_format(Cursor::new(data), format)
+        .into_dimensions()
+        .map_err(|e| bad(e.to_string()))?;
+    if width as u64 * height as u64 > MAX_ATTACHMENT_PIXELS {
+        return Err(bad(format!(
+            "it is {}x{}, more than {} megapixels.",
+            width,
+            height,
+            MAX_ATTACHMENT_PIXELS / 1_000_000
+        )));
+    }
+    Ok((data, format))
+}
+
+/// Check a file's size before downloading or reading it.
+pub fn check_size(bytes: u64) -> Result<(), String> {
+    if bytes > MAX_ATTACHMENT_BYTES as u64 {
+        return Err(format!(
+            "it is {:.1} MB, more than the {} MB limit.",
+            bytes as f64 / (1024.0 * 1024.0),
+            MAX_ATTACHMENT_BYTES / (1024 * 1024)
+        ));
+    }
+    Ok(())
+}
+
+fn format_names() -> String {
+    ATTACHMENT_FORMATS
+        .iter()
+        .map(|(_, name)| *name)
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
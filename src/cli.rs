@@ -0,0 +1,228 @@
+use colored::*;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use mdl_chef::fonts::FontRegistry;
+use mdl_chef::lint::{lint_repo, Severity};
+use mdl_chef::{meme_generator, meme_repository::FormatRepo};
+
+use crate::http_api;
+
+const USAGE: &str = "\
+Usage:
+  mdl_chef
+      Run the Discord bot.
+  mdl_chef render [INPUT] [-o OUTPUT] [--attach FILE]... [--repo FOLDER] [--font FILE]
+      Render an MDL file without connecting to Discord. The image format is
+      the MDL's output, or output_format from Settings.toml (default: PNG, or
+      GIF for animations).
+      INPUT and OUTPUT default to stdin and stdout (also selected by \"-\").
+      Each --attach adds a file MDL can use as attachment:0, attachment:1, ...
+  mdl_chef serve [--listen ADDR] [--repo FOLDER] [--font FILE]
+      Serve the HTTP render API on ADDR (default: http_listen from
+      Settings.toml, or 127.0.0.1:8080).
+  mdl_chef lint-repo [--repo FOLDER] [--font FILE]
+      Check every template in the repo, and the fonts they name if fonts are
+      configured. Exits non-zero if any has errors.
+
+--repo and --font override meme_repo_folder and impact_font_location
+from Settings.toml, which is optional for subcommands.";
+
+/// Run the subcommand named by `args[0]`. Returns the process exit code.
+pub async fn run(args: &[String]) -> i32 {
+    let result = match args[0].as_str() {
+        "render" => render(&args[1..]),
+        "serve" => serve(&args[1..]).await,
+        "lint-repo" => lint(&args[1..]),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            return 0;
+        }
+        other => {
+            eprintln!("Unknown subcommand '{}'.\n\n{}", other, USAGE);
+            return 2;
+        }
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{} {}", "Error:".red().bold(), e);
+            1
+        }
+    }
+}
+
+/// `mdl_chef render`: MDL file or stdin in, image file or stdout out, in the
+/// format chosen by the MDL's `output` or the settings.
+fn render(args: &[String]) -> Result<(), String> {
+    let mut settings = mdl_chef::load_settings(false);
+    let mut input = String::from("-");
+    let mut output = String::from("-");
+    let mut attachments = Vec::new();
+
+    // parse arguments
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => output = option_value(&mut args, arg)?,
+            "--attach" => {
+                let path = option_value(&mut args, arg)?;
+                let data =
+                    std::fs::read(&path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+                attachments.push(data);
+            }
+            "-" => input = arg.clone(),
+            _ if arg.starts_with('-') => parse_repo_option(&mut settings, &mut args, arg)?,
+            _ => input = arg.clone(),
+        }
+    }
+    let frepo = load_repo(settings)?;
+
+    // read and validate the MDL
+    let mut mdlstr = String::new();
+    if input == "-" {
+        io::stdin().read_to_string(&mut mdlstr)
+    } else {
+        std::fs::File::open(&input).and_then(|mut f| f.read_to_string(&mut mdlstr))
+    }
+    .map_err(|e| format!("Could not read {}: {}", input, e))?;
+    let meme = mdl_chef::parse_mdl(&mdlstr).map_err(|e| format!("{}: {}", e.title(), e))?;
+
+    // generate and write out the meme
+    let rendered = meme_generator::render_mdl(&meme, &frepo, &attachments)
+        .map_err(|e| format!("{}: {}", e.title(), e))?;
+    if output == "-" {
+        io::stdout().write_all(&rendered.data)
+    } else {
+        std::fs::write(&output, &rendered.data)
+    }
+    .map_err(|e| format!("Could not write {}: {}", output, e))?;
+    Ok(())
+}
+
+/// `mdl_chef serve`: run the HTTP render API until killed.
+async fn serve(args: &[String]) -> Result<(), String> {
+    let mut settings = mdl_chef::load_settings(false);
+
+    // parse arguments
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => {
+                let addr = option_value(&mut args, arg)?;
+                settings.insert("http_listen".to_string(), addr);
+            }
+            _ => parse_repo_option(&mut settings, &mut args, arg)?,
+        }
+    }
+    let addr: SocketAddr = settings
+        .get("http_listen")
+        .map(String::as_str)
+        .unwrap_or("127.0.0.1:8080")
+        .parse()
+        .map_err(|e| format!("Bad listen address: {}", e))?;
+    let frepo = load_repo(settings)?;
+
+    http_api::serve(frepo, addr)
+        .await
+        .map_err(|e| format!("HTTP server error: {}", e))
+}
+
+/// `mdl_chef lint-repo`: report problems with the templates.
+fn lint(args: &[String]) -> Result<(), String> {
+    let mut settings = mdl_chef::load_settings(false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        parse_repo_option(&mut settings, &mut args, arg)?;
+    }
+    let frepo = FormatRepo::new(repo_folder(&settings)?, "Meme".to_string())
+        .map_err(|e| format!("Failed to create format repo: {}", e))?;
+
+    // fonts are only checked if they're set up, so templates can be linted
+    // without them
+    let fonts = match FontRegistry::from_settings(&settings) {
+        Ok(fonts) => Some(fonts),
+        Err(e) => {
+            println!("{} {}", "Not checking fonts:".yellow(), e);
+            None
+        }
+    };
+    let issues = lint_repo(&frepo, fonts.as_ref());
+    for issue in &issues {
+        match issue.severity {
+            Severity::Error => println!("{}", issue.to_string().red()),
+            Severity::Warning => println!("{}", issue.to_string().yellow()),
+        }
+    }
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    println!(
+        "Checked {} templates: {} errors, {} warnings.",
+        frepo.formats.len(),
+        errors,
+        issues.len() - errors
+    );
+    if errors > 0 {
+        return Err(format!("Found {} errors in the repo.", errors));
+    }
+    Ok(())
+}
+
+/// Handle the options shared by every subcommand which loads the repo.
+fn parse_repo_option<'a>(
+    settings: &mut HashMap<String, String>,
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<(), String> {
+    let key = match option {
+        "--repo" => "meme_repo_folder",
+        "--font" => "impact_font_location",
+        _ => return Err(format!("Unknown option '{}'.", option)),
+    };
+    settings.insert(key.to_string(), option_value(args, option)?);
+    Ok(())
+}
+
+/// Install the settings and load the meme format repository they point to.
+fn load_repo(settings: HashMap<String, String>) -> Result<FormatRepo, String> {
+    if !settings.contains_key("impact_font_location") && !settings.contains_key("fonts") {
+        return Err(
+            "No font given. Pass --font or set impact_font_location or fonts in Settings.toml."
+                .to_string(),
+        );
+    }
+    let meme_repo_folder = repo_folder(&settings)?;
+    mdl_chef::SETTINGS.set(settings).unwrap();
+    FormatRepo::new(meme_repo_folder, "Meme".to_string())
+        .map_err(|e| format!("Failed to create format repo: {}", e))
+}
+
+/// The meme repo folder from the settings, checked to exist.
+fn repo_folder(settings: &HashMap<String, String>) -> Result<PathBuf, String> {
+    let meme_repo_folder = PathBuf::from(
+        settings
+            .get("meme_repo_folder")
+            .map(String::as_str)
+            .unwrap_or("memeformats"),
+    );
+    if !meme_repo_folder.is_dir() {
+        return Err(format!(
+            "Meme repo folder {} is not a directory.",
+            meme_repo_folder.display()
+        ));
+    }
+    Ok(meme_repo_folder)
+}
+
+fn option_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<String, String> {
+    args.next()
+        .cloned()
+        .ok_or(format!("Option {} expects a value.", option))
+}
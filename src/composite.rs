@@ -0,0 +1,225 @@
+use colored::*;
+use image::imageops::{overlay, resize, FilterType};
+use image::{Frame, RgbaImage};
+use std::io;
+use std::io::Write;
+use std::time::Instant;
+
+use crate::error::MdlError;
+use crate::mdl::{Direction, MdlComposite};
+use crate::meme_generator::{draw_meme, DrawnMeme};
+use crate::meme_repository::FormatRepo;
+use crate::output::{self, RenderedMeme};
+
+/// Most pixels a stack or grid may come out as.
+pub const MAX_COMPOSITE_PIXELS: u64 = 40_000_000;
+
+/// Render each panel of a stack or grid, and put them together into one
+/// image. Animated panels contribute their first captioned frame.
+pub fn composite_to_meme(
+    composite: &MdlComposite,
+    frepo: &FormatRepo,
+    attachments: &[Vec<u8>],
+) -> Result<RenderedMeme, MdlError> {
+    // timer
+    let start_time = Instant::now();
+    eprint!(
+        "Compositing a {} of {} panels: ",
+        composite.r#type,
+        composite.panels.len()
+    );
+    io::stderr().flush().unwrap();
+
+    let mut panels = Vec::new();
+    for panel in &composite.panels {
+        let DrawnMeme { mut frames, still } = draw_meme(panel, frepo, attachments)?;
+        panels.push(frames.swap_remove(still).into_buffer());
+    }
+
+    // grids go in rows; stacks are one row or one column
+    let (columns, by_height) = match (composite.r#type.as_str(), composite.direction) {
+        ("grid", _) => (
+            composite
+                .columns
+                .unwrap_or_else(|| (panels.len() as f64).sqrt().ceil() as usize),
+            false,
+        ),
+        (_, Direction::Horizontal) => (panels.len(), true),
+        (_, Direction::Vertical) => (1, false),
+    };
+
+    // scale every panel to the same width, or the same height in a row.
+    // Work out how big that makes everything first, so that a huge layout
+    // is refused instead of allocated.
+    let common = if by_height {
+        composite
+            .height
+            .unwrap_or_else(|| panels.iter().map(|p| p.height()).min().unwrap())
+    } else {
+        composite
+            .width
+            .unwrap_or_else(|| panels.iter().map(|p| p.width()).min().unwrap())
+    } as u64;
+    let sizes: Vec<(u64, u64)> = panels
+        .iter()
+        .map(|panel| {
+            let (width, height) = (panel.width() as u64, panel.height() as u64);
+            if by_height {
+                ((width * common / height).max(1), common)
+            } else {
+                (common, (height * common / width).max(1))
+            }
+        })
+        .collect();
+    let (width, height) = layout_size(&sizes, columns, composite.gutter as u64);
+    if width
+        .checked_mul(height)
+        .is_none_or(|pixels| pixels > MAX_COMPOSITE_PIXELS)
+    {
+        return Err(MdlError::BadComposite {
+            problem: format!(
+                "It would be {}x{} pixels, but the most is {} megapixels. \
+                Try a smaller `width` or `height`, or fewer panels.",
+                width,
+                height,
+                MAX_COMPOSITE_PIXELS / 1_000_000
+            ),
+        });
+    }
+    // everything fits in u32 now
+    let panels: Vec<RgbaImage> = panels
+        .iter()
+        .zip(&sizes)
+        .map(|(panel, (width, height))| {
+            resize(panel, *width as u32, *height as u32, FilterType::Triangle)
+        })
+        .collect();
+
+    let image = arrange(
+        &panels,
+        columns,
+        composite.gutter,
+        composite.background.map_or([255, 255, 255, 255], |c| c.0),
+    );
+    let drawn = DrawnMeme {
+        frames: vec![Frame::new(image)],
+        still: 0,
+    };
+    let rendered = output::encode(drawn, &composite.output)?;
+
+    // end timer
+    eprintln!(
+        "Done. Took {} ms.",
+        start_time.elapsed().as_millis().to_string().yellow()
+    );
+
+    Ok(rendered)
+}
+
+/// Width and height of panels of these sizes laid out by `arrange`.
+fn layout_size(sizes: &[(u64, u64)], columns: usize, gutter: u64) -> (u64, u64) {
+    let rows = sizes.chunks(columns);
+    let width = rows
+        .clone()
+        .map(|row| row.iter().map(|s| s.0).sum::<u64>() + gutter * (row.len() as u64 + 1))
+        .max()
+        .unwrap_or(2 * gutter);
+    let height = rows
+        .clone()
+        .map(|row| row.iter().map(|s| s.1).max().unwrap_or(0))
+        .sum::<u64>()
+        + gutter * (rows.len() as u64 + 1);
+    (width, height)
+}
+
+/// Lay panels out left to right in rows of `columns`, with `gutter` pixels
+/// between and around them. Panels shorter than their row are centered in it.
+fn arrange(panels: &[RgbaImage], columns: usize, gutter: u32, background: [u8; 4]) -> RgbaImage {
+    let sizes: Vec<(u64, u64)> = panels
+        .iter()
+        .map(|p| (p.width() as u64, p.height() as u64))
+        .collect();
+    let (width, height) = layout_size(&sizes, columns, gutter as u64);
+
+    let mut image = RgbaImage::from_pixel(width as u32, height as u32, image::Rgba(background));
+    let mut y = gutter;
+    for row in panels.chunks(columns) {
+        let row_height = row.iter().map(|p| p.height()).max().unwrap();
+        let mut x = gutter;
+        for panel in row {
+            let top = y + (row_height - panel.height()) / 2;
+            overlay(&mut image, panel, x, top);
+            x += panel.width() + gutter;
+        }
+        y += row_height + gutter;
+    }
+    image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    fn panel(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, image::Rgba(color))
+    }
+
+    fn pixel(image: &RgbaImage, x: u32, y: u32) -> [u8; 4] {
+        image.get_pixel(x, y).0
+    }
+
+    #[test]
+    fn one_panel_is_framed_by_the_gutter() {
+        let image = arrange(&[panel(30, 20, RED)], 1, 5, WHITE);
+        assert_eq!(image.dimensions(), (40, 30));
+        assert_eq!(pixel(&image, 4, 4), WHITE);
+        assert_eq!(pixel(&image, 5, 5), RED);
+        assert_eq!(pixel(&image, 34, 24), RED);
+        assert_eq!(pixel(&image, 35, 25), WHITE);
+
+        let image = arrange(&[panel(30, 20, RED)], 4, 0, WHITE);
+        assert_eq!(image.dimensions(), (30, 20));
+        assert!(image.pixels().all(|p| p.0 == RED));
+    }
+
+    #[test]
+    fn ragged_grids_are_as_wide_as_their_widest_row() {
+        let panels = [panel(10, 10, RED), panel(10, 10, RED), panel(10, 10, BLUE)];
+        let image = arrange(&panels, 2, 2, WHITE);
+        assert_eq!(image.dimensions(), (26, 26));
+        // the last row has one panel on the left, and space on the right
+        assert_eq!(pixel(&image, 2, 14), BLUE);
+        assert_eq!(pixel(&image, 11, 23), BLUE);
+        assert_eq!(pixel(&image, 14, 14), WHITE);
+        assert_eq!(pixel(&image, 23, 23), WHITE);
+    }
+
+    #[test]
+    fn short_panels_are_centered_in_their_row() {
+        let panels = [panel(10, 20, RED), panel(10, 10, BLUE)];
+        let image = arrange(&panels, 2, 0, WHITE);
+        assert_eq!(image.dimensions(), (20, 20));
+        assert_eq!(pixel(&image, 15, 4), WHITE);
+        assert_eq!(pixel(&image, 15, 5), BLUE);
+        assert_eq!(pixel(&image, 15, 14), BLUE);
+        assert_eq!(pixel(&image, 15, 15), WHITE);
+    }
+
+    #[test]
+    fn layout_size_does_not_overflow() {
+        let huge = u32::MAX as u64;
+        let sizes = vec![(huge, huge); 16];
+        assert_eq!(
+            layout_size(&sizes, 16, 256),
+            (16 * huge + 17 * 256, huge + 2 * 256)
+        );
+        assert_eq!(
+            layout_size(&sizes, 1, 256),
+            (huge + 2 * 256, 16 * huge + 17 * 256)
+        );
+    }
+}
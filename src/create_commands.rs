@@ -1,5 +1,4 @@
-use serenity::model::interactions::ApplicationCommand;
-use serenity::model::interactions::ApplicationCommandOptionType;
+use serenity::model::application::command::{Command, CommandOptionType};
 use serenity::prelude::*;
 
 const _GUILD_ID: u64 = 552980096315686951;
@@ -7,43 +6,86 @@ const _GUILD_ID: u64 = 552980096315686951;
 pub async fn issue_command_creation(client: &Client) {
     let http = &(client.cache_and_http.http);
 
-    ApplicationCommand::create_global_application_command(http, |a| {
+    Command::create_global_application_command(http, |a| {
         a.name("credits")
             .description("View credits and learn about the technology behind this bot.")
     })
     .await
     .unwrap();
 
-    ApplicationCommand::create_global_application_command(http, |a| {
+    Command::create_global_application_command(http, |a| {
         a.name("help")
             .description("Receive help and usage info on the MDLChef bot.")
     })
     .await
     .unwrap();
 
-    ApplicationCommand::create_global_application_command(http, |a| {
+    Command::create_global_application_command(http, |a| {
         a.name("listmemes")
             .description("List all available meme templates/formats.")
             .create_option(|o| {
                 o.name("memeid")
-                    .description("The identifier of the meme. Asterisk wildcards accepted.")
-                    .kind(ApplicationCommandOptionType::String)
+                    .description(
+                        "Only list identifiers containing this. Asterisk wildcards accepted.",
+                    )
+                    .kind(CommandOptionType::String)
+                    .required(false)
+            })
+            .create_option(|o| {
+                o.name("page")
+                    .description("Which page of the list to show.")
+                    .kind(CommandOptionType::Integer)
                     .required(false)
             })
     })
     .await
     .unwrap();
 
-    ApplicationCommand::create_global_application_command(http, |a| {
+    Command::create_global_application_command(http, |a| {
+        a.name("searchmemes")
+            .description("Search meme templates by name, tag, and description.")
+            .create_option(|o| {
+                o.name("query")
+                    .description("What the meme is about, e.g. \"drake comparison\".")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+            })
+    })
+    .await
+    .unwrap();
+
+    Command::create_global_application_command(http, |a| {
         a.name("memeinfo")
             .description("Get detailed metadata on a meme.")
             .create_option(|o| {
                 o.name("memeid")
-                    .description("The fully-qualified identifier of the meme.")
-                    .kind(ApplicationCommandOptionType::String)
+                    .description("The identifier of the meme.")
+                    .kind(CommandOptionType::String)
                     .required(true)
+                    .set_autocomplete(true)
             })
     })
     .await
     .unwrap();
+
+    Command::create_global_application_command(http, |a| {
+        a.name("make")
+            .description("Make a meme by filling in a form, no MDL needed.")
+            .create_option(|o| {
+                o.name("format")
+                    .description("The identifier of the meme.")
+                    .kind(CommandOptionType::String)
+                    .required(true)
+                    .set_autocomplete(true)
+            })
+    })
+    .await
+    .unwrap();
+
+    Command::create_global_application_command(http, |a| {
+        a.name("reloadmemes")
+            .description("Reload the meme templates from disk. Bot admins only.")
+    })
+    .await
+    .unwrap();
 }
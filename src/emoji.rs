@@ -0,0 +1,165 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Sprites for Unicode emoji, from the `emoji_dir` setting. Files are named
+/// by their code points in hex, joined with `-`, like Twemoji's PNGs:
+/// `1f600.png`, `1f44d-1f3fd.png`.
+static EMOJI_SPRITES: Lazy<HashMap<String, PathBuf>> = Lazy::new(|| {
+    let emoji_dir = match crate::SETTINGS.get().and_then(|s| s.get("emoji_dir")) {
+        Some(dir) => dir,
+        None => return HashMap::new(),
+    };
+    let entries = match std::fs::read_dir(emoji_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Could not read emoji_dir {}: {}", emoji_dir, e);
+            return HashMap::new();
+        }
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let stem = path.file_stem()?.to_str()?.to_lowercase();
+            Some((stem, path))
+        })
+        .collect()
+});
+
+/// Discord custom emoji, like `<:blobcat:1234>` or animated `<a:blobcat:1234>`.
+static CUSTOM_EMOJI: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)<(a?):(\w+):(\d+)>").unwrap());
+
+const ZWJ: char = '\u{200d}';
+const VARIATION_SELECTOR: char = '\u{fe0f}';
+const KEYCAP: char = '\u{20e3}';
+
+/// A piece of caption text: either text for the fonts, or an emoji picture.
+pub enum Segment<'t> {
+    Text(&'t str),
+    Emoji(image::RgbaImage),
+}
+
+/// Split text into runs of text and the emoji we have pictures for.
+/// Emoji without a picture stay text; custom emoji become `:name:`.
+pub fn segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut last = 0;
+    for custom in CUSTOM_EMOJI.captures_iter(text) {
+        let whole = custom.get(0).unwrap();
+        segments.extend(unicode_segments(&text[last..whole.start()]));
+        match custom_emoji(&custom[3]) {
+            Some(sprite) => segments.push(Segment::Emoji(sprite)),
+            // the colons are in the text already, either side of the name
+            None => segments.push(Segment::Text(
+                &text[custom.get(2).unwrap().start() - 1..custom.get(3).unwrap().start()],
+            )),
+        }
+        last = whole.end();
+    }
+    segments.extend(unicode_segments(&text[last..]));
+    segments
+}
+
+/// Picture for a Discord custom emoji, from the `custom_emoji_dir` setting,
+/// where they're saved by ID: `1234.png` or `1234.gif`.
+fn custom_emoji(id: &str) -> Option<image::RgbaImage> {
+    let dir = crate::SETTINGS.get()?.get("custom_emoji_dir")?;
+    ["png", "gif", "webp"]
+        .iter()
+        .map(|extension| Path::new(dir).join(id).with_extension(extension))
+        .find(|path| path.exists())
+        .and_then(|path| image::open(path).ok())
+        .map(|sprite| sprite.into_rgba8())
+}
+
+fn unicode_segments(text: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    if EMOJI_SPRITES.is_empty() {
+        segments.push(Segment::Text(text));
+        return segments;
+    }
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let mut text_start = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let len = emoji_sequence_len(&chars[i..]);
+        let sprite = if len > 0 {
+            sprite_for(chars[i..i + len].iter().map(|(_, c)| *c))
+        } else {
+            None
+        };
+        match sprite {
+            Some(sprite) => {
+                let start = chars[i].0;
+                if start > text_start {
+                    segments.push(Segment::Text(&text[text_start..start]));
+                }
+                segments.push(Segment::Emoji(sprite));
+                i += len;
+                text_start = chars.get(i).map_or(text.len(), |(offset, _)| *offset);
+            }
+            None => i += 1,
+        }
+    }
+    if text_start < text.len() {
+        segments.push(Segment::Text(&text[text_start..]));
+    }
+    segments
+}
+
+/// How many chars at the start of `chars` could form one emoji: a base,
+/// then modifiers, and more bases joined by zero-width joiners. 0 if the
+/// first char can't start an emoji.
+fn emoji_sequence_len(chars: &[(usize, char)]) -> usize {
+    let is_keycap_base = |c: char| c.is_ascii_digit() || c == '#' || c == '*';
+    let is_regional = |c: char| ('\u{1f1e6}'..='\u{1f1ff}').contains(&c);
+    let is_modifier = |c: char| {
+        c == VARIATION_SELECTOR
+            || c == KEYCAP
+            || ('\u{1f3fb}'..='\u{1f3ff}').contains(&c) // skin tones
+            || ('\u{e0020}'..='\u{e007f}').contains(&c) // subdivision flag tags
+    };
+    let c = chars[0].1;
+    if is_keycap_base(c) {
+        return match chars.get(1).map(|(_, c)| *c) {
+            Some(VARIATION_SELECTOR) | Some(KEYCAP) => 1 + modifiers(&chars[1..], is_modifier),
+            _ => 0,
+        };
+    }
+    if c.is_ascii() || c.is_whitespace() {
+        return 0;
+    }
+    // flags are pairs of regional indicators
+    if is_regional(c) && chars.get(1).is_some_and(|(_, c)| is_regional(*c)) {
+        return 2;
+    }
+    let mut len = 1 + modifiers(&chars[1..], is_modifier);
+    while chars.get(len).map(|(_, c)| *c) == Some(ZWJ) && len + 1 < chars.len() {
+        len += 2;
+        len += modifiers(&chars[len..], is_modifier);
+    }
+    len
+}
+
+fn modifiers(chars: &[(usize, char)], is_modifier: impl Fn(char) -> bool) -> usize {
+    chars.iter().take_while(|(_, c)| is_modifier(*c)).count()
+}
+
+/// Load the sprite for an emoji sequence. Sprite sets disagree on whether
+/// names include U+FE0F, so try both.
+fn sprite_for(sequence: impl Iterator<Item = char>) -> Option<image::RgbaImage> {
+    let sequence: Vec<char> = sequence.collect();
+    let name = |chars: &mut dyn Iterator<Item = &char>| {
+        chars
+            .map(|c| format!("{:x}", *c as u32))
+            .collect::<Vec<String>>()
+            .join("-")
+    };
+    let with_selector = name(&mut sequence.iter());
+    let without_selector = name(&mut sequence.iter().filter(|c| **c != VARIATION_SELECTOR));
+    let path = EMOJI_SPRITES
+        .get(&with_selector)
+        .or_else(|| EMOJI_SPRITES.get(&without_selector))?;
+    image::open(path).ok().map(|sprite| sprite.into_rgba8())
+}
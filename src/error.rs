@@ -0,0 +1,183 @@
+use std::fmt;
+
+/// Everything that can go wrong between receiving MDL and producing a meme.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MdlError {
+    /// The MDL was not valid JSON5, or did not match the MDL schema.
+    /// Line and column are one-based, when known.
+    ParseError {
+        message: String,
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    /// The `version` field named a version we don't support.
+    UnsupportedVersion { version: String },
+    /// The `type` field was not one we know how to render.
+    BadType { found: String },
+    /// A stack or grid can't be laid out as written.
+    BadComposite { problem: String },
+    /// The base format is not in the repository.
+    /// Comes with the closest format IDs, best first.
+    UnknownFormat {
+        format: String,
+        suggestions: Vec<String>,
+    },
+    /// The base format matched several formats in the repository.
+    AmbiguousFormat {
+        format: String,
+        candidates: Vec<String>,
+    },
+    /// The MDL filled an insert which the format doesn't have.
+    /// Comes with the closest insert names, best first.
+    UnknownInsert {
+        format: String,
+        insert: String,
+        suggestions: Vec<String>,
+    },
+    /// An insert was given something other than text or an object.
+    WrongInsertType { insert: String },
+    /// A style asked for a font we don't have.
+    /// Comes with the closest font names, best first.
+    UnknownFont {
+        font: String,
+        suggestions: Vec<String>,
+    },
+    /// The MDL used `attachment:N`, but there aren't that many attachments.
+    MissingAttachment { index: usize, count: usize },
+    /// An attachment is too big, or not a picture we can read.
+    BadAttachment { index: usize, problem: String },
+    /// Reading, drawing, or encoding the image failed.
+    RenderError(String),
+}
+
+impl MdlError {
+    /// Short heading for error replies.
+    pub fn title(&self) -> &'static str {
+        match self {
+            MdlError::ParseError { .. } => "MDL Parsing Failure",
+            MdlError::UnsupportedVersion { .. }
+            | MdlError::BadType { .. }
+            | MdlError::BadComposite { .. }
+            | MdlError::WrongInsertType { .. } => "MDL Validation Failure",
+            _ => "Meme Generation Failure",
+        }
+    }
+
+    /// Stable identifier for API consumers to match on.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MdlError::ParseError { .. } => "parse_error",
+            MdlError::UnsupportedVersion { .. } => "unsupported_version",
+            MdlError::BadType { .. } => "bad_type",
+            MdlError::BadComposite { .. } => "bad_composite",
+            MdlError::UnknownFormat { .. } => "unknown_format",
+            MdlError::AmbiguousFormat { .. } => "ambiguous_format",
+            MdlError::UnknownInsert { .. } => "unknown_insert",
+            MdlError::WrongInsertType { .. } => "wrong_insert_type",
+            MdlError::UnknownFont { .. } => "unknown_font",
+            MdlError::MissingAttachment { .. } => "missing_attachment",
+            MdlError::BadAttachment { .. } => "bad_attachment",
+            MdlError::RenderError(_) => "render_error",
+        }
+    }
+}
+
+impl fmt::Display for MdlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MdlError::ParseError { message, .. } => write!(f, "{}", message),
+            MdlError::UnsupportedVersion { version } => write!(
+                f,
+                "`version` field was '{}', not 'MDL/1.1'. This is the only supported \
+                version as of now.",
+                version
+            ),
+            MdlError::BadType { found } => {
+                write!(
+                    f,
+                    "`type` field was '{}', not 'meme', 'stack', or 'grid'.",
+                    found
+                )
+            }
+            MdlError::BadComposite { problem } => write!(f, "{}", problem),
+            MdlError::UnknownFormat {
+                format,
+                suggestions,
+            } => write!(
+                f,
+                "Meme format {} not found.{}",
+                format,
+                did_you_mean(suggestions)
+            ),
+            MdlError::AmbiguousFormat { format, candidates } => write!(
+                f,
+                "Meme format {} is ambiguous. It could be any of {}.",
+                format,
+                candidates
+                    .iter()
+                    .map(|c| format!("\"{}\"", c))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            MdlError::UnknownInsert {
+                insert,
+                suggestions,
+                ..
+            } => write!(
+                f,
+                "This meme does not have an insert called \"{}\".{}",
+                insert,
+                did_you_mean(suggestions)
+            ),
+            MdlError::WrongInsertType { insert } => write!(
+                f,
+                "Insert value for \"{}\" must be a string, or an object with `text` or `image`.",
+                insert
+            ),
+            MdlError::UnknownFont { font, suggestions } => write!(
+                f,
+                "Font \"{}\" not found.{}",
+                font,
+                did_you_mean(suggestions)
+            ),
+            MdlError::MissingAttachment { index, count } => write!(
+                f,
+                "There is no attachment:{}. {} files were attached, numbered from 0.",
+                index, count
+            ),
+            MdlError::BadAttachment { index, problem } => {
+                write!(f, "attachment:{} can't be used: {}", index, problem)
+            }
+            MdlError::RenderError(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for MdlError {}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("\"{}\"", s)).collect();
+    match quoted.len() {
+        0 => String::new(),
+        1 => format!(" Did you mean {}?", quoted[0]),
+        _ => format!(" Did you mean one of {}?", quoted.join(", ")),
+    }
+}
+
+impl From<json5::Error> for MdlError {
+    fn from(e: json5::Error) -> Self {
+        match e {
+            json5::Error::Message { msg, location } => MdlError::ParseError {
+                message: msg,
+                line: location.as_ref().map(|l| l.line),
+                column: location.as_ref().map(|l| l.column),
+            },
+        }
+    }
+}
+
+impl From<image::ImageError> for MdlError {
+    fn from(e: image::ImageError) -> Self {
+        MdlError::RenderError(e.to_string())
+    }
+}
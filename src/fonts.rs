@@ -0,0 +1,198 @@
+use fontdue::{Font, FontSettings};
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::error::MdlError;
+use crate::suggest::{suggest, MAX_SUGGESTIONS};
+
+/// Every font named in the settings, loaded once.
+static FONT_REGISTRY: OnceCell<FontRegistry> = OnceCell::new();
+
+/// Fonts by name, plus the chain of fonts to fall back on for characters
+/// the chosen font doesn't have.
+///
+/// Configured in the settings:
+/// - `impact_font_location`: file for the font called `Impact`
+/// - `fonts`: more fonts, as `Name=path, Name=path`. A path may be a font file
+///   or a directory, whose font files are all tried in order of file name.
+/// - `default_font`: font for text which doesn't name one. `Impact` if it
+///   exists, otherwise the first of `fonts`.
+/// - `font_fallback`: names of fonts to try, in order, for characters missing
+///   from the chosen font, e.g. `Noto, Symbols`.
+pub struct FontRegistry {
+    /// every loaded font; the layout refers to them by index
+    pub fonts: Vec<Font>,
+    /// font names as configured, with their indices into `fonts`
+    names: Vec<(String, Vec<usize>)>,
+    default: String,
+    fallback: Vec<usize>,
+}
+
+impl FontRegistry {
+    /// The registry for the global settings, loaded on first use.
+    pub fn global() -> Result<&'static FontRegistry, MdlError> {
+        FONT_REGISTRY.get_or_try_init(|| {
+            let settings = crate::SETTINGS
+                .get()
+                .ok_or_else(|| MdlError::RenderError("Settings were not loaded.".to_string()))?;
+            FontRegistry::from_settings(settings).map_err(MdlError::RenderError)
+        })
+    }
+
+    /// Load all the fonts the settings name. Fails if any can't be loaded.
+    pub fn from_settings(settings: &HashMap<String, String>) -> Result<FontRegistry, String> {
+        let mut registry = FontRegistry {
+            fonts: Vec::new(),
+            names: Vec::new(),
+            default: String::new(),
+            fallback: Vec::new(),
+        };
+        if let Some(path) = settings.get("impact_font_location") {
+            registry.add("Impact", Path::new(path))?;
+        }
+        for entry in list(settings.get("fonts")) {
+            let split = entry.find('=').ok_or_else(|| {
+                format!("Expected fonts as `Name=path, Name=path`, got `{}`.", entry)
+            })?;
+            let (name, path) = (&entry[..split], &entry[split + 1..]);
+            registry.add(name.trim(), Path::new(path.trim()))?;
+        }
+        registry.default =
+            match settings.get("default_font") {
+                Some(name) => name.trim().to_string(),
+                None if registry.find("Impact").is_some() => "Impact".to_string(),
+                None => match registry.names.first() {
+                    Some((name, _)) => name.clone(),
+                    None => return Err(
+                        "No fonts configured. Set impact_font_location or fonts in Settings.toml."
+                            .to_string(),
+                    ),
+                },
+            };
+        registry
+            .chain(Some(&registry.default))
+            .map_err(|e| e.to_string())?;
+        for name in list(settings.get("font_fallback")) {
+            let indices = registry
+                .chain(Some(name))
+                .map_err(|e| format!("In font_fallback: {}", e))?;
+            registry.fallback.extend(indices);
+        }
+        Ok(registry)
+    }
+
+    /// Register a font file, or every font file in a directory, under `name`.
+    fn add(&mut self, name: &str, path: &Path) -> Result<(), String> {
+        let files = if path.is_dir() {
+            let mut files: Vec<_> = std::fs::read_dir(path)
+                .map_err(|e| format!("Could not read font directory {}: {}", path.display(), e))?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| {
+                    let extension = p.extension().and_then(|e| e.to_str()).unwrap_or("");
+                    ["ttf", "otf", "ttc"].contains(&extension.to_lowercase().as_str())
+                })
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+        if files.is_empty() {
+            return Err(format!("No font files in {}.", path.display()));
+        }
+        let mut indices = Vec::new();
+        for file in files {
+            let bytes = std::fs::read(&file)
+                .map_err(|e| format!("Font file not found at {}: {}", file.display(), e))?;
+            let font = Font::from_bytes(bytes, FontSettings::default()) // EXPENSIVE ~50ms
+                .map_err(|e| format!("Bad font file {}: {}", file.display(), e))?;
+            indices.push(self.fonts.len());
+            self.fonts.push(font);
+        }
+        self.names.push((name.to_string(), indices));
+        Ok(())
+    }
+
+    fn find(&self, name: &str) -> Option<&Vec<usize>> {
+        self.names
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, indices)| indices)
+    }
+
+    /// Indices of the fonts to try for text in font `name`, ignoring case:
+    /// the font itself, then the fallbacks. `None` is the default font.
+    pub fn chain(&self, name: Option<&str>) -> Result<Vec<usize>, MdlError> {
+        let name = name.unwrap_or(&self.default);
+        let mut chain = self
+            .find(name)
+            .ok_or_else(|| MdlError::UnknownFont {
+                font: name.to_string(),
+                suggestions: suggest(name, self.names.iter().map(|(n, _)| n), MAX_SUGGESTIONS),
+            })?
+            .clone();
+        for i in &self.fallback {
+            if !chain.contains(i) {
+                chain.push(*i);
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Split text into runs which can each be drawn with a single font: the
+    /// first font of `chain` which has the character. Characters no font has
+    /// stay with the first font. Whitespace goes with whatever precedes it.
+    pub fn runs<'t>(&self, text: &'t str, chain: &[usize]) -> Vec<(&'t str, usize)> {
+        let mut runs = Vec::new();
+        let mut run_start = 0;
+        let mut run_font = chain[0];
+        for (i, c) in text.char_indices() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let font = *chain
+                .iter()
+                .find(|f| self.fonts[**f].lookup_glyph_index(c) != 0)
+                .unwrap_or(&chain[0]);
+            if font != run_font {
+                if i > run_start {
+                    runs.push((&text[run_start..i], run_font));
+                }
+                run_start = i;
+                run_font = font;
+            }
+        }
+        if run_start < text.len() {
+            runs.push((&text[run_start..], run_font));
+        }
+        runs
+    }
+
+    /// A registry which knows the names of fonts without loading any, for
+    /// checking names. The first is the default.
+    #[cfg(test)]
+    pub(crate) fn names_only(names: &[&str]) -> FontRegistry {
+        FontRegistry {
+            fonts: Vec::new(),
+            names: names
+                .iter()
+                .map(|name| (name.to_string(), Vec::new()))
+                .collect(),
+            default: names[0].to_string(),
+            fallback: Vec::new(),
+        }
+    }
+}
+
+/// Items of a comma-separated setting, if it's set.
+fn list(setting: Option<&String>) -> Vec<&str> {
+    setting
+        .map(|s| {
+            s.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,397 @@
+use hyper::body::HttpBody;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use colored::*;
+use percent_encoding::percent_decode_str;
+use serde_json::json;
+
+use mdl_chef::{
+    attachments::MAX_ATTACHMENT_BYTES, mdl::MdlDocument, meme_generator,
+    meme_repository::FormatRepo, MdlError,
+};
+
+/// MDL snippets are small; refuse to buffer anything bigger than this.
+const MAX_MDL_BYTES: usize = 64 * 1024;
+
+/// Uploads with attachments are bigger, but not without limit.
+const MAX_UPLOAD_BYTES: usize = MAX_MDL_BYTES + 4 * MAX_ATTACHMENT_BYTES;
+
+/// Serve the render API until the server fails.
+///
+/// - `POST /render` takes an MDL body and returns the meme, with the
+///   Content-Type of its output format (`image/png`, `image/jpeg`,
+///   `image/webp`, or `image/gif`). To attach files, send
+///   `multipart/form-data` instead: a part named `mdl`, and the files in the
+///   order `attachment:0`, `attachment:1`, ... refer to.
+/// - `GET /formats` lists every format and its insert names.
+/// - `GET /formats/{memeid}` describes a single format.
+///
+/// Failures are returned as `{"error": {"title": ..., "message": ...}}`,
+/// plus a `kind` from [`MdlError::kind`] and any known parse location.
+pub async fn serve(frepo: FormatRepo, addr: SocketAddr) -> Result<(), hyper::Error> {
+    let frepo = Arc::new(frepo);
+    let make_service = make_service_fn(move |_conn| {
+        let frepo = frepo.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle(frepo.clone(), req))) }
+    });
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    println!("Serving HTTP API on {}", addr.to_string().blue().bold());
+    server.await
+}
+
+async fn handle(frepo: Arc<FormatRepo>, req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let path = req.uri().path().to_string();
+    println!(
+        "--- {}\nGot HTTP request {} {}.",
+        chrono::Local::now()
+            .format("%a %b %e %T")
+            .to_string()
+            .bright_black(),
+        req.method(),
+        path.yellow()
+    );
+    let response = match (req.method(), path.as_str()) {
+        (&Method::POST, "/render") => respond_render(frepo, req).await,
+        (&Method::GET, "/formats") => respond_formats(&frepo),
+        (&Method::GET, p) if p.starts_with("/formats/") => {
+            respond_format_info(&frepo, &p["/formats/".len()..])
+        }
+        _ => reply_error(StatusCode::NOT_FOUND, "Not Found", "No such endpoint."),
+    };
+    Ok(response)
+}
+
+async fn respond_render(frepo: Arc<FormatRepo>, req: Request<Body>) -> Response<Body> {
+    let boundary = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.starts_with("multipart/form-data"))
+        .and_then(|v| {
+            v.split(';')
+                .map(str::trim)
+                .find_map(|p| p.strip_prefix("boundary="))
+        })
+        .map(|b| b.trim_matches('"').to_string());
+
+    // Read the body, up to the limit
+    let limit = if boundary.is_some() {
+        MAX_UPLOAD_BYTES
+    } else {
+        MAX_MDL_BYTES
+    };
+    let mut body = req.into_body();
+    let mut bodybytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = match chunk {
+            Ok(c) => c,
+            Err(e) => return reply_error(StatusCode::BAD_REQUEST, "Bad Request", &e.to_string()),
+        };
+        if bodybytes.len() + chunk.len() > limit {
+            return reply_error(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "MDL Parsing Failure",
+                &format!("Body exceeds {} bytes.", limit),
+            );
+        }
+        bodybytes.extend_from_slice(&chunk);
+    }
+
+    // Separate the MDL from the attachments
+    let (mdlbytes, attachments) = match &boundary {
+        Some(boundary) => {
+            let parts = match multipart_parts(&bodybytes, boundary) {
+                Some(parts) => parts,
+                None => {
+                    return reply_error(
+                        StatusCode::BAD_REQUEST,
+                        "Bad Request",
+                        "Could not read the multipart/form-data body.",
+                    )
+                }
+            };
+            let mut mdl = None;
+            let mut attachments = Vec::new();
+            for (name, content) in parts {
+                if name == "mdl" {
+                    mdl = Some(content.to_vec());
+                } else {
+                    attachments.push(content.to_vec());
+                }
+            }
+            match mdl {
+                Some(mdl) => (mdl, attachments),
+                None => {
+                    return reply_error(
+                        StatusCode::BAD_REQUEST,
+                        "MDL Parsing Failure",
+                        "No part named \"mdl\" in the form.",
+                    )
+                }
+            }
+        }
+        None => (bodybytes, Vec::new()),
+    };
+    let mdlstr = match String::from_utf8(mdlbytes) {
+        Ok(s) => s,
+        Err(_) => {
+            return reply_error(
+                StatusCode::BAD_REQUEST,
+                "MDL Parsing Failure",
+                "MDL is not valid UTF-8.",
+            )
+        }
+    };
+
+    // Attempt deserialization and validation, same as for Discord messages
+    let meme: MdlDocument = match mdl_chef::parse_mdl(&mdlstr) {
+        Ok(v) => v,
+        Err(e) => return reply_mdl_error(StatusCode::BAD_REQUEST, &e),
+    };
+
+    // Generate the meme off the async runtime, it takes a while
+    let memegen_result = tokio::task::spawn_blocking(move || {
+        meme_generator::render_mdl(&meme, &frepo, &attachments)
+    })
+    .await
+    .unwrap_or_else(|e| Err(MdlError::RenderError(e.to_string())));
+    match memegen_result {
+        Ok(rendered) => Response::builder()
+            .header(CONTENT_TYPE, rendered.mime)
+            .body(Body::from(rendered.data))
+            .unwrap(),
+        Err(e) => reply_mdl_error(StatusCode::UNPROCESSABLE_ENTITY, &e),
+    }
+}
+
+/// Split a `multipart/form-data` body into each part's name and content,
+/// in order. `None` if it's malformed.
+fn multipart_parts<'b>(body: &'b [u8], boundary: &str) -> Option<Vec<(String, &'b [u8])>> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let next_delimiter = [b"\r\n", delimiter.as_slice()].concat();
+    let mut parts = Vec::new();
+    let mut rest = &body[find(body, &delimiter)? + delimiter.len()..];
+    // the last delimiter has `--` after it
+    while !rest.starts_with(b"--") {
+        rest = rest.strip_prefix(b"\r\n")?;
+        let headers_end = find(rest, b"\r\n\r\n")?;
+        let headers = std::str::from_utf8(&rest[..headers_end]).ok()?;
+        let name = headers
+            .lines()
+            .find(|l| l.to_ascii_lowercase().starts_with("content-disposition:"))?
+            .split(';')
+            .map(str::trim)
+            .find_map(|p| p.strip_prefix("name="))?
+            .trim_matches('"')
+            .to_string();
+        let content = &rest[headers_end + 4..];
+        let content_end = find(content, &next_delimiter)?;
+        parts.push((name, &content[..content_end]));
+        rest = &content[content_end + next_delimiter.len()..];
+    }
+    Some(parts)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn respond_formats(frepo: &FormatRepo) -> Response<Body> {
+    let mut memeids: Vec<&String> = frepo.formats.keys().collect();
+    memeids.sort();
+    let formats: Vec<serde_json::Value> = memeids
+        .into_iter()
+        .map(|memeid| {
+            let format = &frepo.formats[memeid];
+            let mut insert_names: Vec<&String> = format.inserts.keys().collect();
+            insert_names.sort();
+            json!({
+                "memeid": memeid,
+                "aliases": format.aliases,
+                "inserts": insert_names,
+            })
+        })
+        .collect();
+    reply_json(StatusCode::OK, &json!({ "formats": formats }))
+}
+
+/// `segment` is the memeID as it appears in the path, percent-encoded.
+fn respond_format_info(frepo: &FormatRepo, segment: &str) -> Response<Body> {
+    let memeid = match percent_decode_str(segment).decode_utf8() {
+        Ok(memeid) => memeid,
+        Err(_) => {
+            return reply_error(
+                StatusCode::BAD_REQUEST,
+                "Bad Request",
+                "The meme ID is not valid UTF-8.",
+            )
+        }
+    };
+    let format = match frepo.resolve(&memeid) {
+        Ok(f) => f,
+        Err(e) => return reply_mdl_error(StatusCode::NOT_FOUND, &e),
+    };
+    let inserts: serde_json::Map<String, serde_json::Value> = format
+        .inserts
+        .iter()
+        .map(|(name, coords)| (name.clone(), json!({ "coords": coords })))
+        .collect();
+    reply_json(
+        StatusCode::OK,
+        &json!({
+            "memeid": format.memeid,
+            "name": format.name,
+            "aliases": format.aliases,
+            "description": format.description,
+            "author": format.author,
+            "source": format.source,
+            "license": format.license,
+            "tags": format.tags,
+            "keywords": format.keywords,
+            "inserts": inserts,
+        }),
+    )
+}
+
+fn reply_json(status: StatusCode, value: &serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(Body::from(value.to_string()))
+        .unwrap()
+}
+
+fn reply_mdl_error(status: StatusCode, e: &MdlError) -> Response<Body> {
+    println!(
+        "Replying with error! {} {}",
+        e.title().red().bold(),
+        e.to_string().red()
+    );
+    let mut error = json!({
+        "title": e.title(),
+        "kind": e.kind(),
+        "message": e.to_string(),
+    });
+    if let MdlError::ParseError {
+        line: Some(line),
+        column: Some(column),
+        ..
+    } = e
+    {
+        error["line"] = json!(line);
+        error["column"] = json!(column);
+    }
+    reply_json(status, &json!({ "error": error }))
+}
+
+fn reply_error(status: StatusCode, title: &str, error: &str) -> Response<Body> {
+    println!(
+        "Replying with error! {} {}",
+        title.red().bold(),
+        error.red()
+    );
+    reply_json(
+        status,
+        &json!({ "error": { "title": title, "message": error } }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(name: &str, content: &str) -> String {
+        format!(
+            "--b0undary\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+            name, content
+        )
+    }
+
+    #[test]
+    fn parts_come_back_in_order() {
+        let body = format!(
+            "{}{}--b0undary--\r\n",
+            part("mdl", "{ base: \"drake\" }"),
+            part("picture", "\u{89}PNG\r\n--b0und")
+        );
+        let parts = multipart_parts(body.as_bytes(), "b0undary").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0], ("mdl".to_string(), &b"{ base: \"drake\" }"[..]));
+        assert_eq!(parts[1].0, "picture");
+        assert_eq!(parts[1].1, "\u{89}PNG\r\n--b0und".as_bytes());
+    }
+
+    #[test]
+    fn preamble_headers_and_empty_parts_are_handled() {
+        let body = "ignored preamble\r\n--b0undary\r\n\
+            Content-Type: text/plain\r\n\
+            content-disposition: form-data; name=mdl; filename=\"m.mdl\"\r\n\r\n\
+            \r\n--b0undary--";
+        let parts = multipart_parts(body.as_bytes(), "b0undary").unwrap();
+        assert_eq!(parts, vec![("mdl".to_string(), &b""[..])]);
+        assert_eq!(
+            multipart_parts(b"--b0undary--", "b0undary"),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn malformed_bodies_are_rejected() {
+        let unterminated = part("mdl", "{}");
+        let missing_name = "--b0undary\r\nContent-Disposition: form-data\r\n\r\n{}\r\n--b0undary--";
+        let no_headers_end = "--b0undary\r\nContent-Disposition: form-data; name=\"mdl\"\r\n{}";
+        for body in &[
+            "",
+            "no boundary at all",
+            "--b0undary",
+            "--b0undaryX\r\n",
+            unterminated.as_str(),
+            missing_name,
+            no_headers_end,
+        ] {
+            assert_eq!(
+                multipart_parts(body.as_bytes(), "b0undary"),
+                None,
+                "{:?}",
+                body
+            );
+        }
+        let wrong_boundary = format!("{}--b0undary--", part("mdl", "{}"));
+        assert_eq!(multipart_parts(wrong_boundary.as_bytes(), "other"), None);
+    }
+
+    #[test]
+    fn non_utf8_headers_are_rejected() {
+        let body =
+            b"--b0undary\r\nContent-Disposition: form-data; name=\"\xff\"\r\n\r\nx\r\n--b0undary--";
+        assert_eq!(multipart_parts(body, "b0undary"), None);
+    }
+
+    #[test]
+    fn format_ids_are_percent_decoded() {
+        let dir = std::env::temp_dir().join(format!("mdl_chef_http_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in &["Space Cat.png", "ドレイク.png"] {
+            image::RgbaImage::new(10, 10).save(dir.join(file)).unwrap();
+        }
+        let frepo = FormatRepo::new(dir.clone(), "Meme".to_string()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let status = |segment| respond_format_info(&frepo, segment).status();
+        assert_eq!(status("Meme.Space%20Cat"), StatusCode::OK);
+        assert_eq!(
+            status("Meme.%E3%83%89%E3%83%AC%E3%82%A4%E3%82%AF"),
+            StatusCode::OK
+        );
+        assert_eq!(status("Meme.Space%20Dog"), StatusCode::NOT_FOUND);
+        assert_eq!(status("Meme.%FF"), StatusCode::BAD_REQUEST);
+    }
+}
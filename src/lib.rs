@@ -0,0 +1,85 @@
+//! Chefs up memes using MDL (Meme Description Language).
+//!
+//! This is the rendering core of the MDLChef bot, usable without Discord:
+//!
+//! ```no_run
+//! use std::path::PathBuf;
+//!
+//! mdl_chef::SETTINGS
+//!     .set(mdl_chef::load_settings(true))
+//!     .unwrap();
+//! let frepo = mdl_chef::FormatRepo::new(PathBuf::from("memeformats"), "Meme".to_string())?;
+//! let meme = mdl_chef::parse_mdl(r#"{
+//!     version: "MDL/1.1",
+//!     type: "meme",
+//!     base: "Meme.UtopianWorld",
+//!     caption: { topText: "the world if", bottomText: "memes all used MDL" }
+//! }"#)?;
+//! let rendered = mdl_chef::render_mdl(&meme, &frepo, &[])?;
+//! std::fs::write(format!("meme.{}", rendered.extension), &rendered.data)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+use once_cell::sync::OnceCell;
+use std::collections::HashMap;
+
+pub mod animation;
+pub mod attachments;
+pub mod composite;
+pub mod emoji;
+pub mod error;
+pub mod fonts;
+pub mod lint;
+pub mod mdl;
+pub mod meme_generator;
+pub mod meme_repository;
+pub mod metadata;
+pub mod output;
+pub mod search;
+pub mod style;
+pub mod suggest;
+
+pub use error::MdlError;
+pub use mdl::{MdlDocument, MdlMeme};
+pub use meme_generator::{mdl_to_meme, render_mdl};
+pub use meme_repository::FormatRepo;
+pub use output::RenderedMeme;
+
+/// Settings shared by the renderer. Must be set before the first meme is
+/// generated, since the font is loaded from `impact_font_location`.
+pub static SETTINGS: OnceCell<HashMap<String, String>> = OnceCell::new();
+
+/// Read Settings.toml from the current directory.
+/// Subcommands may run without one, in which case `required` is false.
+pub fn load_settings(required: bool) -> HashMap<String, String> {
+    let mut settings = config::Config::default();
+    settings
+        .merge(config::File::with_name("Settings").required(required))
+        .expect("Expected Settings.toml file in current directory");
+    settings.try_into::<HashMap<String, String>>().unwrap()
+}
+
+/// Parse an MDL string (JSON5) and validate it.
+pub fn parse_mdl(mdlstr: &str) -> Result<MdlDocument, MdlError> {
+    // the type decides what else to expect. Inserts are checked here too,
+    // since serde's errors can't say which insert has the wrong type.
+    #[derive(serde::Deserialize)]
+    struct Header {
+        r#type: String,
+        inserts: Option<serde_json::Value>,
+        panels: Option<serde_json::Value>,
+    }
+    let header: Header = json5::from_str(mdlstr)?;
+    mdl::check_insert_types(header.inserts.as_ref())?;
+    if let Some(serde_json::Value::Array(panels)) = &header.panels {
+        for panel in panels {
+            mdl::check_insert_types(panel.get("inserts"))?;
+        }
+    }
+    let document = match header.r#type.as_str() {
+        "stack" | "grid" => MdlDocument::Composite(json5::from_str(mdlstr)?),
+        _ => MdlDocument::Meme(Box::new(json5::from_str(mdlstr)?)),
+    };
+    document.validate()?;
+    Ok(document)
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+
+use crate::animation;
+use crate::fonts::FontRegistry;
+use crate::meme_repository::{FormatRepo, MemeFormat};
+use crate::style::CaptionStyle;
+
+/// How bad a lint finding is. Errors fail `lint-repo`, warnings don't.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Something wrong with a template.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintIssue {
+    pub severity: Severity,
+    /// memeID of the format, or path of a file which didn't load
+    pub subject: String,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.subject, self.message)
+    }
+}
+
+/// Check every template in the repo, including files which failed to load.
+/// Fonts named by templates are checked against `fonts`, if given. Issues
+/// come sorted by subject.
+pub fn lint_repo(frepo: &FormatRepo, fonts: Option<&FontRegistry>) -> Vec<LintIssue> {
+    let mut issues: Vec<LintIssue> = frepo
+        .diagnostics
+        .iter()
+        .map(|d| LintIssue {
+            severity: Severity::Error,
+            subject: d.path.display().to_string(),
+            message: d.problem.clone(),
+        })
+        .chain(frepo.warnings.iter().map(|d| LintIssue {
+            severity: Severity::Warning,
+            subject: d.path.display().to_string(),
+            message: d.problem.clone(),
+        }))
+        .collect();
+    for format in frepo.formats.values() {
+        issues.extend(lint_format(format, fonts));
+    }
+    issues.sort_by(|a, b| a.subject.cmp(&b.subject));
+    issues
+}
+
+/// Check that a format's inserts are sensibly named and fit in its image,
+/// and that it can be drawn: its fonts exist, and its frames are in the
+/// animation.
+pub fn lint_format(format: &MemeFormat, fonts: Option<&FontRegistry>) -> Vec<LintIssue> {
+    let mut issues = Vec::new();
+    let mut issue = |severity, message| {
+        issues.push(LintIssue {
+            severity,
+            subject: format.memeid.clone(),
+            message,
+        })
+    };
+    let (width, height) = match image::image_dimensions(&format.image_path) {
+        Ok(dimensions) => dimensions,
+        Err(e) => {
+            issue(Severity::Error, format!("Unreadable image: {}", e));
+            return issues;
+        }
+    };
+
+    if let Some(fonts) = fonts {
+        let mut styles: Vec<(&str, &CaptionStyle)> = format
+            .insert_styles
+            .iter()
+            .map(|(name, style)| (name.as_str(), style))
+            .collect();
+        styles.sort_by_key(|(name, _)| *name);
+        for (name, style) in std::iter::once(("", &format.style)).chain(styles) {
+            if let Err(e) = fonts.chain(style.font.as_deref()) {
+                let context = match name {
+                    "" => "Style".to_string(),
+                    name => format!("Style of insert \"{}\"", name),
+                };
+                issue(Severity::Error, format!("{}: {}", context, e));
+            }
+        }
+    }
+
+    if let Some(range) = format.frames {
+        match animation::open(&format.image_path) {
+            Ok(frames) if frames.len() > 1 && range.first >= frames.len() => issue(
+                Severity::Error,
+                format!(
+                    "Frames [{}, {}] are past the end of the animation, which has {} frames.",
+                    range.first,
+                    range.last,
+                    frames.len()
+                ),
+            ),
+            Ok(frames) if frames.len() > 1 && range.last >= frames.len() => issue(
+                Severity::Warning,
+                format!(
+                    "Frames [{}, {}] go past the end of the animation, which has {} frames.",
+                    range.first,
+                    range.last,
+                    frames.len()
+                ),
+            ),
+            Ok(_) => {}
+            Err(e) => issue(Severity::Error, format!("Unreadable image: {}", e)),
+        }
+    }
+
+    let mut names: Vec<&String> = format.inserts.keys().collect();
+    names.sort();
+    for (i, name) in names.iter().enumerate() {
+        let ((x1, y1), (x2, y2)) = format.inserts[*name];
+        if !is_identifier(name) {
+            issue(
+                Severity::Error,
+                format!(
+                    "Insert name \"{}\" is not an identifier (letters, digits, and _, \
+                    not starting with a digit).",
+                    name
+                ),
+            );
+        }
+        if x2 <= x1 || y2 <= y1 {
+            // the generator subtracts the corners, this would underflow
+            issue(
+                Severity::Error,
+                format!(
+                    "Insert \"{}\" has no area: the second corner ({}, {}) must be right \
+                    of and below the first ({}, {}).",
+                    name, x2, y2, x1, y1
+                ),
+            );
+            continue;
+        }
+        if x2 > width || y2 > height {
+            issue(
+                Severity::Error,
+                format!(
+                    "Insert \"{}\" reaches ({}, {}), outside the {}x{} image.",
+                    name, x2, y2, width, height
+                ),
+            );
+        }
+        for other in &names[i + 1..] {
+            let ((ox1, oy1), (ox2, oy2)) = format.inserts[*other];
+            if x1 < ox2 && ox1 < x2 && y1 < oy2 && oy1 < y2 {
+                issue(
+                    Severity::Warning,
+                    format!("Inserts \"{}\" and \"{}\" overlap.", name, other),
+                );
+            }
+        }
+    }
+    issues
+}
+
+/// Insert names are written as unquoted JSON5 keys in MDL.
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Frame, RgbaImage};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A fresh, empty directory for a test repo.
+    fn repo_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mdl_chef_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Add a 100x100 template called `name` to the repo in `dir`.
+    fn template(dir: &Path, name: &str, metadata: &str) {
+        RgbaImage::new(100, 100)
+            .save(dir.join(format!("{}.png", name)))
+            .unwrap();
+        fs::write(dir.join(format!("{}.meme", name)), metadata).unwrap();
+    }
+
+    /// Add a 100x100, 3 frame animated template called `name`.
+    fn animated_template(dir: &Path, name: &str, metadata: &str) {
+        let frames = vec![Frame::new(RgbaImage::new(100, 100)); 3];
+        let gif = animation::encode_gif(&frames, usize::MAX).unwrap();
+        fs::write(dir.join(format!("{}.gif", name)), gif).unwrap();
+        fs::write(dir.join(format!("{}.meme", name)), metadata).unwrap();
+    }
+
+    #[test]
+    fn every_broken_template_is_reported() {
+        let dir = repo_dir("lint");
+        template(&dir, "Good", "{inserts: {a: {coords: [[0, 0], [50, 50]]}}}");
+        template(&dir, "Malformed", "{inserts: ");
+        template(
+            &dir,
+            "BadName",
+            "{inserts: {'1st': {coords: [[0, 0], [50, 50]]}}}",
+        );
+        template(
+            &dir,
+            "Outside",
+            "{inserts: {a: {coords: [[0, 0], [150, 50]]}}}",
+        );
+        template(
+            &dir,
+            "Overlap",
+            "{inserts: {a: {coords: [[0, 0], [50, 50]]}, b: {coords: [[25, 25], [75, 75]]}}}",
+        );
+        template(&dir, "StyleFont", "{style: {font: 'Nope'}, inserts: {}}");
+        template(
+            &dir,
+            "InsertFont",
+            "{inserts: {a: {coords: [[0, 0], [50, 50]], font: 'Nope'}}}",
+        );
+        animated_template(&dir, "PastEnd", "{inserts: {}, frames: [5, 6]}");
+        animated_template(&dir, "Overrun", "{inserts: {}, frames: [1, 5]}");
+        let frepo = FormatRepo::new(dir.clone(), "Lint".to_string()).unwrap();
+        let fonts = FontRegistry::names_only(&["Impact", "Comic"]);
+
+        let issues = lint_repo(&frepo, Some(&fonts));
+        fs::remove_dir_all(&dir).unwrap();
+        let found: Vec<(Severity, &str)> = issues
+            .iter()
+            .map(|issue| (issue.severity, issue.subject.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (Severity::Error, "Lint.BadName"),
+                (Severity::Error, "Lint.InsertFont"),
+                (Severity::Error, "Lint.Outside"),
+                (Severity::Warning, "Lint.Overlap"),
+                (Severity::Warning, "Lint.Overrun"),
+                (Severity::Error, "Lint.PastEnd"),
+                (Severity::Error, "Lint.StyleFont"),
+                (Severity::Error, "Malformed.meme"),
+            ]
+        );
+        assert!(issues[1].message.contains("Style of insert \"a\""));
+        assert!(issues[1].message.contains("Nope"));
+        assert!(issues[5].message.contains("has 3 frames"));
+        assert!(issues[6].message.starts_with("Style:"));
+    }
+
+    #[test]
+    fn fonts_are_only_checked_when_given() {
+        let dir = repo_dir("lint_no_fonts");
+        template(&dir, "StyleFont", "{style: {font: 'Nope'}, inserts: {}}");
+        let frepo = FormatRepo::new(dir.clone(), "Lint".to_string()).unwrap();
+
+        let issues = lint_repo(&frepo, None);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(issues, Vec::new());
+    }
+
+    #[test]
+    fn identifiers() {
+        assert!(is_identifier("topText"));
+        assert!(is_identifier("_2"));
+        assert!(!is_identifier("2nd"));
+        assert!(!is_identifier("top text"));
+        assert!(!is_identifier(""));
+    }
+}
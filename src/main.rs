@@ -1,5 +1,5 @@
 use colored::*;
-use once_cell::sync::OnceCell;
+use mdl_chef::{load_settings, meme_repository, SETTINGS};
 use regex::RegexBuilder;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -7,25 +7,25 @@ use std::path::PathBuf;
 use serenity::{
     async_trait,
     model::{
+        application::interaction::Interaction,
         channel::Message,
         gateway::{Activity, Ready},
-        interactions::Interaction,
     },
     prelude::*,
 };
 
 //mod create_commands;
+mod cli;
 mod create_commands;
-mod mdl;
-mod meme_generator;
-mod meme_repository;
+mod http_api;
 mod respond_commands;
 mod respond_mdl;
+mod shared_repo;
 
-pub static SETTINGS: OnceCell<HashMap<String, String>> = OnceCell::new();
+use shared_repo::SharedRepo;
 
 struct Handler {
-    pub meme_format_repo: meme_repository::FormatRepo,
+    pub meme_format_repo: SharedRepo,
     pub settings: HashMap<String, String>,
 }
 
@@ -54,7 +54,7 @@ impl EventHandler for Handler {
                 ctx.http.broadcast_typing(msg.channel_id.0).await.unwrap();
                 // NOTE: this ^^^ breaks interaction response
                 // since iteration response cannot occur while typing :(
-                respond_mdl::respond_mdl(&self.meme_format_repo, ctx, &msg, mdlstr).await;
+                respond_mdl::respond_mdl(&self.meme_format_repo.get(), ctx, &msg, mdlstr).await;
             }
         }
     }
@@ -72,18 +72,26 @@ impl EventHandler for Handler {
     // Triggered when receiving interaction.
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         //println!("{:#?}", interaction);
-        respond_commands::interaction_create(&self.meme_format_repo, ctx, interaction).await;
+        respond_commands::interaction_create(
+            &self.meme_format_repo,
+            &self.settings,
+            ctx,
+            interaction,
+        )
+        .await;
     }
 }
 
 #[tokio::main]
 async fn main() {
+    // Run a subcommand instead of the bot, if one was given
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(cli::run(&args).await);
+    }
+
     // Get settings file
-    let mut settings = config::Config::default();
-    settings
-        .merge(config::File::with_name("Settings"))
-        .expect("Expected Settings.toml file in current directory");
-    let settings: HashMap<String, String> = settings.try_into::<HashMap<String, String>>().unwrap();
+    let settings = load_settings(true);
     SETTINGS.set(settings.clone()).unwrap();
 
     // Get bot token, app ID, and meme repo directory location from the settings
@@ -105,14 +113,17 @@ async fn main() {
         meme_repository::FormatRepo::new(PathBuf::from(meme_repo_folder), "Meme".to_string())
             .expect("Died: Failed to create format repo.");
     let handler = Handler {
-        meme_format_repo,
+        meme_format_repo: SharedRepo::new(meme_format_repo),
         settings,
     };
 
     // Create a new instance of the Client, logging in as a bot. This will
     // automatically prepend your bot token with "Bot ", which is a requirement
     // by Discord for bot users.
-    let mut client = Client::builder(&token)
+    // MDL is read from messages, so this needs the Message Content intent
+    // enabled for the bot in the Discord developer portal
+    let intents = GatewayIntents::non_privileged() | GatewayIntents::MESSAGE_CONTENT;
+    let mut client = Client::builder(&token, intents)
         .application_id(application_id)
         .event_handler(handler)
         .await
@@ -2,10 +2,16 @@ use serde::de::{self, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer};
 use serde_json::Value;
 
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 use std::fmt;
 use std::marker::PhantomData;
 use std::str::FromStr;
 
+use crate::animation::FrameRange;
+use crate::error::MdlError;
+use crate::style::{CaptionStyle, Color};
+
 #[derive(Deserialize, Debug)]
 pub struct MdlMeme {
     /// Version should be MDL/1.1 for now
@@ -13,7 +19,6 @@ pub struct MdlMeme {
     /// Should always be "meme"
     pub r#type: String,
     /// Contains the base format
-    #[serde(deserialize_with = "string_or_struct")]
     pub base: MdlBase,
     /// Either a string for single caption at default position,
     /// or an object with top text and bottom text
@@ -21,34 +26,288 @@ pub struct MdlMeme {
     #[serde(deserialize_with = "string_or_struct")]
     pub caption: MdlCaption,
     /// Inserts object
-    pub inserts: Option<Value>,
+    pub inserts: Option<BTreeMap<String, MdlInsert>>,
+    /// For animated bases, the frames to draw on, as `[first, last]`
+    pub frames: Option<FrameRange>,
+    /// How to encode the image. Anything left out uses the server's default
+    #[serde(default)]
+    #[serde(deserialize_with = "string_or_struct")]
+    pub output: MdlOutput,
+}
+
+impl MdlMeme {
+    /// Check the fields serde can't check for us.
+    pub fn validate(&self) -> Result<(), MdlError> {
+        if self.r#type != "meme" {
+            return Err(MdlError::BadType {
+                found: self.r#type.clone(),
+            });
+        }
+        if self.version != "MDL/1.1" {
+            return Err(MdlError::UnsupportedVersion {
+                version: self.version.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Which files attached to the request rendering needs, by index.
+    pub fn attachment_indexes(&self) -> BTreeSet<usize> {
+        let base = match self.base {
+            MdlBase::Image(ImageSource::Attachment(index)) => Some(index),
+            _ => None,
+        };
+        let inserts =
+            self.inserts
+                .iter()
+                .flat_map(|i| i.values())
+                .filter_map(|insert| match insert {
+                    MdlInsert::Image(MdlImage {
+                        source: ImageSource::Attachment(index),
+                        ..
+                    }) => Some(*index),
+                    _ => None,
+                });
+        base.into_iter().chain(inserts).collect()
+    }
+}
+
+/// Most panels in one composite, so they render in reasonable time.
+pub const MAX_PANELS: usize = 16;
+
+/// Largest `width` or `height` a composite may scale its panels to.
+pub const MAX_PANEL_SIZE: u32 = 4096;
+
+/// Widest gutter a composite may have between its panels.
+pub const MAX_GUTTER: u32 = 256;
+
+/// A whole MDL message: one meme, or several put together.
+#[derive(Debug)]
+pub enum MdlDocument {
+    Meme(Box<MdlMeme>),
+    Composite(MdlComposite),
+}
+
+impl MdlDocument {
+    /// Check the fields serde can't check for us.
+    pub fn validate(&self) -> Result<(), MdlError> {
+        match self {
+            MdlDocument::Meme(meme) => meme.validate(),
+            MdlDocument::Composite(composite) => composite.validate(),
+        }
+    }
+
+    /// Which files attached to the request rendering needs, by index.
+    pub fn attachment_indexes(&self) -> BTreeSet<usize> {
+        match self {
+            MdlDocument::Meme(meme) => meme.attachment_indexes(),
+            MdlDocument::Composite(composite) => composite
+                .panels
+                .iter()
+                .flat_map(MdlMeme::attachment_indexes)
+                .collect(),
+        }
+    }
 }
 
+/// Several memes in one image, either stacked in a line (`type: "stack"`)
+/// or in rows and columns (`type: "grid"`). The panels are ordinary memes.
 #[derive(Deserialize, Debug)]
-pub struct MdlBase {
-    pub format: String,
+pub struct MdlComposite {
+    /// Version should be MDL/1.1 for now
+    pub version: String,
+    /// "stack" or "grid"
+    pub r#type: String,
+    /// For stacks, which way they go
+    #[serde(default)]
+    pub direction: Direction,
+    /// For grids, panels per row. By default, enough to make a square
+    pub columns: Option<usize>,
+    /// Space between and around the panels, in pixels
+    #[serde(default)]
+    pub gutter: u32,
+    /// Color of the gutters, white by default
+    pub background: Option<Color>,
+    /// Width to scale every panel to, except in horizontal stacks.
+    /// By default the narrowest panel's width
+    pub width: Option<u32>,
+    /// Height to scale every panel to in horizontal stacks.
+    /// By default the shortest panel's height
+    pub height: Option<u32>,
+    /// The memes to put together, in reading order
+    pub panels: Vec<MdlMeme>,
+    /// How to encode the image. The panels' own `output` is ignored
+    #[serde(default)]
+    #[serde(deserialize_with = "string_or_struct")]
+    pub output: MdlOutput,
+}
+
+impl MdlComposite {
+    /// Check the fields serde can't check for us, and the panels.
+    pub fn validate(&self) -> Result<(), MdlError> {
+        if self.r#type != "stack" && self.r#type != "grid" {
+            return Err(MdlError::BadType {
+                found: self.r#type.clone(),
+            });
+        }
+        if self.version != "MDL/1.1" {
+            return Err(MdlError::UnsupportedVersion {
+                version: self.version.clone(),
+            });
+        }
+        let bad = |problem: String| Err(MdlError::BadComposite { problem });
+        if self.panels.is_empty() {
+            return bad("A stack or grid needs at least one panel.".to_string());
+        }
+        if self.panels.len() > MAX_PANELS {
+            return bad(format!(
+                "A stack or grid can have at most {} panels, not {}.",
+                MAX_PANELS,
+                self.panels.len()
+            ));
+        }
+        if self.gutter > MAX_GUTTER {
+            return bad(format!(
+                "`gutter` can be at most {} pixels, not {}.",
+                MAX_GUTTER, self.gutter
+            ));
+        }
+        if self.columns == Some(0) {
+            return bad("`columns` must be at least 1.".to_string());
+        }
+        for size in self.width.iter().chain(self.height.iter()) {
+            if !(1..=MAX_PANEL_SIZE).contains(size) {
+                return bad(format!(
+                    "Panels can be scaled to between 1 and {} pixels, not {}.",
+                    MAX_PANEL_SIZE, size
+                ));
+            }
+        }
+        self.panels.iter().try_for_each(MdlMeme::validate)
+    }
+}
+
+/// Which way a stack goes.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// top to bottom
+    #[default]
+    Vertical,
+    /// left to right
+    Horizontal,
+}
+
+/// The picture to draw on: a format from the repo, `attachment:N` for
+/// captions on a picture of your own, or a blank canvas. Either a string, or
+/// an object like `{ format: "drake" }`, `{ image: "attachment:0" }`, or
+/// `{ canvas: { width: 800, height: 200, color: "white" } }`.
+#[derive(Debug)]
+pub enum MdlBase {
+    Image(ImageSource),
+    Canvas(MdlCanvas),
 }
 
-impl FromStr for MdlBase {
+impl fmt::Display for MdlBase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MdlBase::Image(source) => write!(f, "{}", source),
+            MdlBase::Canvas(canvas) => write!(f, "{}x{} canvas", canvas.width, canvas.height),
+        }
+    }
+}
+
+/// Largest `width` or `height` of a blank canvas.
+pub const MAX_CANVAS_SIZE: u32 = 4096;
+
+/// A plain background to write on, for text-only memes and headers.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct MdlCanvas {
+    pub width: u32,
+    pub height: u32,
+    /// white by default
+    #[serde(default = "white")]
+    pub color: Color,
+}
+
+fn white() -> Color {
+    Color([255, 255, 255, 255])
+}
+
+/// The object form of `MdlBase`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BaseObject {
+    format: Option<String>,
+    image: Option<ImageSource>,
+    canvas: Option<MdlCanvas>,
+}
+
+impl FromStr for BaseObject {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(MdlBase {
-            format: s.to_string(),
+        Ok(BaseObject {
+            format: None,
+            image: Some(ImageSource::try_from(s.to_string())?),
+            canvas: None,
         })
     }
     type Err = String;
 }
 
+impl<'de> Deserialize<'de> for MdlBase {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object: BaseObject = string_or_struct(deserializer)?;
+        match (object.format, object.image, object.canvas) {
+            (Some(format), None, None) => Ok(MdlBase::Image(ImageSource::Format(format))),
+            (None, Some(image), None) => Ok(MdlBase::Image(image)),
+            (None, None, Some(canvas)) => {
+                for size in &[canvas.width, canvas.height] {
+                    if !(1..=MAX_CANVAS_SIZE).contains(size) {
+                        return Err(de::Error::custom(format!(
+                            "canvas sizes are between 1 and {} pixels, not {}",
+                            MAX_CANVAS_SIZE, size
+                        )));
+                    }
+                }
+                Ok(MdlBase::Canvas(canvas))
+            }
+            (None, None, None) => Err(de::Error::custom("missing field `format`")),
+            _ => Err(de::Error::custom(
+                "expected one of `format`, `image`, or `canvas`",
+            )),
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MdlCaption {
     #[serde(alias = "bottom")]
     #[serde(alias = "south_text")]
-    pub bottom_text: Option<String>,
+    pub bottom_text: Option<MdlText>,
     #[serde(alias = "top")]
     #[serde(alias = "north_text")]
-    pub top_text: Option<String>,
+    pub top_text: Option<MdlText>,
     #[serde(alias = "middle")]
-    pub center_text: Option<String>,
+    pub center_text: Option<MdlText>,
+    /// Whether the top and bottom text go over the picture or on bars
+    #[serde(default)]
+    pub mode: CaptionMode,
+}
+
+/// Where top and bottom text go.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionMode {
+    /// over the picture, classic style
+    #[default]
+    Overlay,
+    /// on white bars added above and below the picture, which is left whole
+    Bar,
 }
 
 fn empty_mdl_caption() -> MdlCaption {
@@ -56,20 +315,296 @@ fn empty_mdl_caption() -> MdlCaption {
         bottom_text: None,
         center_text: None,
         top_text: None,
+        mode: CaptionMode::Overlay,
     }
 }
 
 impl FromStr for MdlCaption {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(MdlCaption {
-            bottom_text: Some(s.to_string()),
+            bottom_text: Some(MdlText {
+                text: s.to_string(),
+                style: CaptionStyle::default(),
+            }),
             top_text: None,
             center_text: None,
+            mode: CaptionMode::Overlay,
         })
     }
     type Err = String;
 }
 
+/// Text for a caption or insert. Either a plain string, or an object with
+/// style overrides, like `{ text: "no", color: "red", size: 40 }`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdlText {
+    pub text: String,
+    pub style: CaptionStyle,
+}
+
+/// An insert: text, or a picture like `{ image: "attachment:0", fit: "contain" }`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MdlInsert {
+    Text(MdlText),
+    Image(MdlImage),
+}
+
+/// A picture to put in an insert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MdlImage {
+    pub source: ImageSource,
+    pub fit: Fit,
+}
+
+/// Where a picture comes from: `attachment:N` for the Nth (from 0) file
+/// attached to the request, otherwise a format in the repo, by anything
+/// `FormatRepo::resolve` accepts.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "String")]
+pub enum ImageSource {
+    Attachment(usize),
+    Format(String),
+}
+
+impl TryFrom<String> for ImageSource {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.strip_prefix("attachment:") {
+            Some(index) => index
+                .trim()
+                .parse()
+                .map(ImageSource::Attachment)
+                .map_err(|_| {
+                    format!(
+                        "expected `attachment:` and a number, like `attachment:0`, got `{}`",
+                        s
+                    )
+                }),
+            None => Ok(ImageSource::Format(s)),
+        }
+    }
+}
+
+impl fmt::Display for ImageSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageSource::Attachment(index) => write!(f, "attachment:{}", index),
+            ImageSource::Format(format) => write!(f, "{}", format),
+        }
+    }
+}
+
+/// How a picture is sized to its insert.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// as big as fits entirely, centered
+    Contain,
+    /// as small as fills the insert, with the edges cropped off
+    #[default]
+    Cover,
+    /// exactly the insert's size, ignoring aspect ratio
+    Stretch,
+}
+
+/// The object form of `MdlText` and `MdlInsert`.
+#[derive(Deserialize)]
+struct TextObject {
+    text: Option<String>,
+    image: Option<ImageSource>,
+    fit: Option<Fit>,
+    #[serde(flatten)]
+    style: CaptionStyle,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, Value>,
+}
+
+impl FromStr for TextObject {
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(TextObject {
+            text: Some(s.to_string()),
+            image: None,
+            fit: None,
+            style: CaptionStyle::default(),
+            unknown: BTreeMap::new(),
+        })
+    }
+    type Err = String;
+}
+
+impl TextObject {
+    fn into_insert<E: de::Error>(self) -> Result<MdlInsert, E> {
+        // a misspelled style would otherwise do nothing, silently
+        if let Some(field) = self.unknown.keys().next() {
+            return Err(E::custom(format!(
+                "unknown field `{}`, expected `text`, `image`, or a style field",
+                field
+            )));
+        }
+        match (self.text, self.image) {
+            (Some(text), None) if self.fit.is_none() => Ok(MdlInsert::Text(MdlText {
+                text,
+                style: self.style,
+            })),
+            (Some(_), None) => Err(E::custom("`fit` only applies to `image`")),
+            (None, Some(source)) if self.style == CaptionStyle::default() => {
+                Ok(MdlInsert::Image(MdlImage {
+                    source,
+                    fit: self.fit.unwrap_or_default(),
+                }))
+            }
+            (None, Some(_)) => Err(E::custom("style fields only apply to `text`")),
+            (Some(_), Some(_)) => Err(E::custom("expected `text` or `image`, not both")),
+            (None, None) => Err(E::custom("missing field `text`")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MdlInsert {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object: TextObject = string_or_struct(deserializer)?;
+        object.into_insert()
+    }
+}
+
+impl<'de> Deserialize<'de> for MdlText {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let object: TextObject = string_or_struct(deserializer)?;
+        match object.into_insert()? {
+            MdlInsert::Text(text) => Ok(text),
+            MdlInsert::Image(_) => Err(de::Error::custom("images can only go in inserts")),
+        }
+    }
+}
+
+/// Check that every insert is text or an object, so a number or list can be
+/// reported by the insert's name.
+pub fn check_insert_types(inserts: Option<&Value>) -> Result<(), MdlError> {
+    if let Some(Value::Object(inserts)) = inserts {
+        for (name, value) in inserts {
+            if !value.is_string() && !value.is_object() {
+                return Err(MdlError::WrongInsertType {
+                    insert: name.clone(),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How to encode the meme. Either just the format, like `"jpeg"`, or an
+/// object like `{ format: "jpeg", quality: 80, maxWidth: 1000 }`.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MdlOutput {
+    pub format: Option<OutputFormat>,
+    /// for JPEG and WebP, from 1 (smallest) to 100 (best)
+    #[serde(default, deserialize_with = "quality")]
+    pub quality: Option<u8>,
+    /// the image is scaled down to fit within these
+    #[serde(default, deserialize_with = "max_size")]
+    pub max_width: Option<u32>,
+    #[serde(default, deserialize_with = "max_size")]
+    pub max_height: Option<u32>,
+}
+
+impl FromStr for MdlOutput {
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(MdlOutput {
+            format: Some(s.parse()?),
+            ..MdlOutput::default()
+        })
+    }
+    type Err = String;
+}
+
+fn quality<'de, D>(deserializer: D) -> Result<Option<u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let quality = u8::deserialize(deserializer)?;
+    if !(1..=100).contains(&quality) {
+        return Err(de::Error::custom(format!(
+            "quality {} is not between 1 and 100",
+            quality
+        )));
+    }
+    Ok(Some(quality))
+}
+
+fn max_size<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let size = u32::deserialize(deserializer)?;
+    if size == 0 {
+        return Err(de::Error::custom("maximum sizes must be at least 1 pixel"));
+    }
+    Ok(Some(size))
+}
+
+/// An image encoding we can produce.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub enum OutputFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Gif,
+}
+
+impl OutputFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Gif => "gif",
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::Gif => "image/gif",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::WebP),
+            "gif" => Ok(OutputFormat::Gif),
+            _ => Err(format!(
+                "expected output format png, jpeg, webp, or gif, got `{}`",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for OutputFormat {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
 /// source: https://serde.rs/string-or-struct.html
 fn string_or_struct<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
@@ -97,7 +632,7 @@ where
         where
             E: de::Error,
         {
-            Ok(FromStr::from_str(value).unwrap())
+            FromStr::from_str(value).map_err(E::custom)
         }
 
         fn visit_map<M>(self, map: M) -> Result<T, M::Error>
@@ -114,3 +649,53 @@ where
 
     deserializer.deserialize_any(StringOrStruct(PhantomData))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_formats_parse_case_insensitively() {
+        assert_eq!("png".parse(), Ok(OutputFormat::Png));
+        assert_eq!("JPEG".parse(), Ok(OutputFormat::Jpeg));
+        assert_eq!("jpg".parse(), Ok(OutputFormat::Jpeg));
+        assert_eq!("WebP".parse(), Ok(OutputFormat::WebP));
+        assert_eq!("gif".parse(), Ok(OutputFormat::Gif));
+        assert_eq!(OutputFormat::Jpeg.extension(), "jpg");
+        assert_eq!(OutputFormat::WebP.mime(), "image/webp");
+    }
+
+    #[test]
+    fn unknown_output_formats_are_rejected() {
+        for s in &["", "bmp", " png", "png ", "jpe", "ｐｎｇ"] {
+            assert_eq!(
+                s.parse::<OutputFormat>(),
+                Err(format!(
+                    "expected output format png, jpeg, webp, or gif, got `{}`",
+                    s
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn output_options_are_validated() {
+        let output: MdlOutput =
+            json5::from_str("{ format: \"jpg\", quality: 80, maxWidth: 1000 }").unwrap();
+        assert_eq!(output.format, Some(OutputFormat::Jpeg));
+        assert_eq!(output.quality, Some(80));
+        assert_eq!(output.max_width, Some(1000));
+        assert_eq!(output.max_height, None);
+
+        for bad in &[
+            "{ quality: 0 }",
+            "{ quality: 101 }",
+            "{ maxWidth: 0 }",
+            "{ maxHeight: 0 }",
+            "{ format: \"tiff\" }",
+            "{ size: 10 }",
+        ] {
+            assert!(json5::from_str::<MdlOutput>(bad).is_err(), "{}", bad);
+        }
+    }
+}
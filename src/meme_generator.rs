@@ -1,143 +1,479 @@
 use colored::*;
-use image::{EncodableLayout, ImageBuffer, ImageEncoder};
-use std::collections::HashMap;
-use std::error::Error;
+use image::ImageBuffer;
 use std::io;
 use std::io::Write;
 use std::ops::Not;
 use std::time::Instant;
 
 use fontdue::layout::*;
-use fontdue::*;
 
+use crate::animation;
+use crate::attachments;
+use crate::composite;
+use crate::emoji::{self, Segment};
+use crate::error::MdlError;
+use crate::fonts::FontRegistry;
+use crate::mdl::{
+    CaptionMode, Fit, ImageSource, MdlBase, MdlDocument, MdlInsert, MdlMeme, MdlText,
+};
 use crate::meme_repository::FormatRepo;
+use crate::output::{self, RenderedMeme};
+use crate::style::{Align, CaptionStyle, Color, VAlign};
+use crate::suggest::{suggest, MAX_SUGGESTIONS};
 
-use once_cell::sync::Lazy;
-static IMPACT_FONT: Lazy<Font> = Lazy::new(|| {
-    let settings: &HashMap<String, String> = crate::SETTINGS
-        .get()
-        .expect("Settings.toml hashmap not found.");
-    let impact_font_location = settings
-        .get("impact_font_location")
-        .expect("Error: impact_font_location not found in Settings.toml");
-    let impact = std::fs::read(impact_font_location)
-        .expect(&format!("Font file not found at {}", impact_font_location).to_string());
-    let impact = Font::from_bytes(impact, FontSettings::default()).unwrap(); // EXPENSIVE ~50ms
-    return impact;
-});
-
-/// Convert an MDL object to a PNG (represented as Vec<u8>).
+/// Render a parsed MDL message, whether one meme or several put together.
+pub fn render_mdl(
+    mdl: &MdlDocument,
+    frepo: &FormatRepo,
+    attachments: &[Vec<u8>],
+) -> Result<RenderedMeme, MdlError> {
+    match mdl {
+        MdlDocument::Meme(meme) => mdl_to_meme(meme, frepo, attachments),
+        MdlDocument::Composite(composite) => {
+            composite::composite_to_meme(composite, frepo, attachments)
+        }
+    }
+}
+
+/// Convert an MDL object to an image, encoded as its `output` says: by
+/// default a PNG, or a GIF if the base is animated.
+/// `attachments` are the files MDL can refer to as `attachment:N`.
 pub fn mdl_to_meme(
-    mdl: &super::mdl::MdlMeme,
+    mdl: &MdlMeme,
     frepo: &FormatRepo,
-) -> Result<Vec<u8>, Box<dyn Error>> {
+    attachments: &[Vec<u8>],
+) -> Result<RenderedMeme, MdlError> {
     // timer
     let start_time = Instant::now();
 
-    // get format
-    let fmt = frepo
-        .formats
-        .get(&mdl.base.format)
-        .ok_or(format!("Meme format {} not found.", &mdl.base.format))?;
-    print!("Generating {}... ", &mdl.base.format.blue());
-    io::stdout().flush().unwrap();
-
-    // read in the base image, get width and height
-    let base_image_path = fmt.image_path.to_str().unwrap();
-    let mut img = image::open(base_image_path)?.into_rgba8();
-    let base_image_w = img.width();
-    let base_image_h = img.height();
-    let caption_height = (base_image_h / 3) as u32 - 20;
-
-    // apply captions which exist
-    if let Some(capt) = &mdl.caption.top_text {
-        img = apply_caption(
-            img,
-            capt,
-            20,
-            10,
-            base_image_w - 40,
-            caption_height,
-            VerticalAlign::Top,
-        );
+    let drawn = draw_meme(mdl, frepo, attachments)?;
+    let rendered = output::encode(drawn, &mdl.output)?;
+
+    // end timer
+    eprintln!(
+        "Done. Took {} ms.",
+        start_time.elapsed().as_millis().to_string().yellow()
+    );
+
+    Ok(rendered)
+}
+
+/// A meme which is drawn, but not encoded yet.
+pub struct DrawnMeme {
+    /// one, or one per frame of an animated base
+    pub frames: Vec<image::Frame>,
+    /// the frame to show when the meme can't move: the first one with the
+    /// captions and inserts on it
+    pub still: usize,
+}
+
+/// Draw an MDL object's captions and inserts on its base, without encoding
+/// it.
+pub fn draw_meme(
+    mdl: &MdlMeme,
+    frepo: &FormatRepo,
+    attachments: &[Vec<u8>],
+) -> Result<DrawnMeme, MdlError> {
+    // read in the base image, get width and height. Pictures of your own
+    // and canvases have no format: no inserts, and the default style.
+    let (fmt, mut frames) = match &mdl.base {
+        MdlBase::Image(ImageSource::Format(format)) => {
+            let fmt = frepo.resolve(format)?;
+            eprint!("Generating {}... ", &fmt.memeid.blue());
+            io::stderr().flush().unwrap();
+            let frames = animation::open(&fmt.image_path).map_err(|e| {
+                MdlError::RenderError(format!("Could not read {}: {}", fmt.memeid, e))
+            })?;
+            (Some(fmt), frames)
+        }
+        MdlBase::Image(ImageSource::Attachment(index)) => {
+            eprint!("Generating on {}... ", mdl.base.to_string().blue());
+            io::stderr().flush().unwrap();
+            (None, attachments::load_frames(attachments, *index)?)
+        }
+        MdlBase::Canvas(canvas) => {
+            eprint!("Generating on a {}... ", mdl.base.to_string().blue());
+            io::stderr().flush().unwrap();
+            let blank = image::RgbaImage::from_pixel(
+                canvas.width,
+                canvas.height,
+                image::Rgba(canvas.color.0),
+            );
+            (None, vec![image::Frame::new(blank)])
+        }
+    };
+    let default_style = CaptionStyle::default();
+    let base_style = fmt.map_or(&default_style, |fmt| &fmt.style);
+    let base_image_w = frames[0].buffer().width();
+    let base_image_h = frames[0].buffer().height();
+
+    // in bar mode, top and bottom text go on bars added above and below the
+    // picture, in black on white unless styled otherwise
+    let bar_style = CaptionStyle {
+        color: Some(Color([0, 0, 0, 255])),
+        outline_width: Some(0),
+        ..CaptionStyle::default()
+    };
+    let bar = |capt: &Option<MdlText>| -> Result<Option<Bar>, MdlError> {
+        match (mdl.caption.mode, capt) {
+            (CaptionMode::Bar, Some(capt)) => {
+                let look = text_look(&capt.style.layered_over(&bar_style), VAlign::Middle)?;
+                Ok(Some(Bar::new(&capt.text, look, base_image_w)))
+            }
+            _ => Ok(None),
+        }
+    };
+    let top_bar = bar(&mdl.caption.top_text)?;
+    let bottom_bar = bar(&mdl.caption.bottom_text)?;
+    let top_bar_h = top_bar.as_ref().map_or(0, |bar| bar.height);
+    let bottom_bar_h = bottom_bar.as_ref().map_or(0, |bar| bar.height);
+    if top_bar_h + bottom_bar_h > 0 {
+        let height = top_bar_h
+            .checked_add(base_image_h)
+            .and_then(|h| h.checked_add(bottom_bar_h))
+            .ok_or_else(|| {
+                MdlError::RenderError("The caption bars would make the meme too tall.".into())
+            })?;
+        // the bars are the same on every frame, so draw them once
+        let mut background =
+            image::RgbaImage::from_pixel(base_image_w, height, image::Rgba([255, 255, 255, 255]));
+        if let Some(bar) = top_bar {
+            background = bar.draw(background, 0);
+        }
+        if let Some(bar) = bottom_bar {
+            background = bar.draw(background, top_bar_h + base_image_h);
+        }
+        for frame in &mut frames {
+            let mut extended = background.clone();
+            image::imageops::overlay(&mut extended, frame.buffer(), 0, top_bar_h);
+            *frame = image::Frame::from_parts(extended, 0, 0, frame.delay());
+        }
     }
-    if let Some(capt) = &mdl.caption.center_text {
-        img = apply_caption(
-            img,
-            capt,
-            20,
+
+    // draw straight onto a still image. For animations, draw once onto a
+    // transparent layer, then put that on the frames.
+    let animated = frames.len() > 1;
+    let mut img = if animated {
+        let (width, height) = frames[0].buffer().dimensions();
+        image::RgbaImage::new(width, height)
+    } else {
+        frames.pop().unwrap().into_buffer()
+    };
+    let caption_height = (base_image_h / 3).saturating_sub(20);
+
+    // apply captions which exist, and aren't on bars
+    let captions = [
+        (&mdl.caption.top_text, 10, VAlign::Top, top_bar_h == 0),
+        (
+            &mdl.caption.center_text,
             caption_height + 10,
-            base_image_w - 40,
-            caption_height,
-            VerticalAlign::Middle,
-        );
-    }
-    if let Some(capt) = &mdl.caption.bottom_text {
+            VAlign::Middle,
+            true,
+        ),
+        (
+            &mdl.caption.bottom_text,
+            base_image_h.saturating_sub(caption_height + 10),
+            VAlign::Bottom,
+            bottom_bar_h == 0,
+        ),
+    ];
+    let overlaid = captions
+        .iter()
+        .filter(|(capt, .., overlay)| capt.is_some() && *overlay);
+    // a canvas has no picture to keep clear, so its captions share all of
+    // it, top to bottom
+    let canvas_band = base_image_h.saturating_sub(20) / (overlaid.clone().count() as u32).max(1);
+    for (placed, (capt, y_top, valign, _)) in overlaid.enumerate() {
+        let capt = capt.as_ref().unwrap();
+        let (y_top, height, valign) = match mdl.base {
+            MdlBase::Canvas(_) => (
+                10 + placed as u32 * canvas_band,
+                canvas_band,
+                VAlign::Middle,
+            ),
+            MdlBase::Image(_) => (*y_top, caption_height, *valign),
+        };
+        let look = text_look(&capt.style.layered_over(base_style), valign)?;
         img = apply_caption(
             img,
-            capt,
+            &capt.text,
             20,
-            base_image_h - caption_height - 10,
-            base_image_w - 40,
-            caption_height,
-            VerticalAlign::Bottom,
+            top_bar_h + y_top,
+            base_image_w.saturating_sub(40),
+            height,
+            &look,
         );
     }
 
     // apply inserts which exist
     if let Some(inserts) = &mdl.inserts {
-        let inserts = inserts
-            .as_object()
-            .ok_or("Key 'inserts' should be an object.")?;
         for (insert_name, insert_val) in inserts {
-            let coords = fmt.inserts.get(insert_name).ok_or(format!(
-                "This meme does not have an insert called \"{}\"",
-                insert_name
-            ))?;
-            let insert_capt = insert_val
-                .as_str()
-                .ok_or("Insert value must be a string.")?;
-            img = apply_caption(
-                img,
-                insert_capt,
-                coords.0 .0,
-                coords.0 .1,
-                coords.1 .0 - coords.0 .0,
-                coords.1 .1 - coords.0 .1,
-                VerticalAlign::Middle,
-            );
+            let fmt = fmt.ok_or_else(|| MdlError::UnknownInsert {
+                format: mdl.base.to_string(),
+                insert: insert_name.clone(),
+                suggestions: Vec::new(),
+            })?;
+            let coords = fmt
+                .inserts
+                .get(insert_name)
+                .ok_or_else(|| MdlError::UnknownInsert {
+                    format: fmt.memeid.clone(),
+                    insert: insert_name.clone(),
+                    suggestions: suggest(insert_name, fmt.inserts.keys(), MAX_SUGGESTIONS),
+                })?;
+            let (x, y) = (coords.0 .0, top_bar_h + coords.0 .1);
+            let (width, height) = match (
+                coords.1 .0.checked_sub(coords.0 .0),
+                coords.1 .1.checked_sub(coords.0 .1),
+            ) {
+                (Some(width), Some(height)) => (width, height),
+                _ => {
+                    return Err(MdlError::RenderError(format!(
+                        "Insert \"{}\" of {} has its corners the wrong way round.",
+                        insert_name, fmt.memeid
+                    )))
+                }
+            };
+            match insert_val {
+                MdlInsert::Text(text) => {
+                    // the MDL's style wins over the insert's, which wins over the format's
+                    let style = match fmt.insert_styles.get(insert_name) {
+                        Some(insert_style) => insert_style.layered_over(&fmt.style),
+                        None => fmt.style.clone(),
+                    };
+                    let look = text_look(&text.style.layered_over(&style), VAlign::Middle)?;
+                    img = apply_caption(img, &text.text, x, y, width, height, &look);
+                }
+                MdlInsert::Image(picture) => {
+                    let picture_img = load_image(&picture.source, frepo, attachments)?;
+                    apply_image(&mut img, &picture_img, x, y, width, height, picture.fit);
+                }
+            }
         }
     }
 
-    // encode to PNG and output to vector
-    let mut png_out = Vec::<u8>::new();
-    image::codecs::png::PngEncoder::new(&mut png_out).write_image(
-        img.as_bytes(),
-        img.width(),
-        img.height(),
-        image::ColorType::Rgba8,
-    )?;
+    let mut still = 0;
+    if animated {
+        // the MDL's frame range wins over the format's, the default is all
+        let range = mdl.frames.or_else(|| fmt.and_then(|fmt| fmt.frames));
+        if let Some(range) = range {
+            if range.first >= frames.len() {
+                return Err(MdlError::RenderError(format!(
+                    "Frames [{}, {}] are past the end of the animation, which has {} frames.",
+                    range.first,
+                    range.last,
+                    frames.len()
+                )));
+            }
+            still = range.first;
+        }
+        for (i, frame) in frames.iter_mut().enumerate() {
+            if range.is_none_or(|range| range.contains(i)) {
+                image::imageops::overlay(frame.buffer_mut(), &img, 0, 0);
+            }
+        }
+    } else {
+        frames = vec![image::Frame::new(img)];
+    }
+    Ok(DrawnMeme { frames, still })
+}
 
-    // end timer
-    println!(
-        "Done. Took {} ms.",
-        start_time.elapsed().as_millis().to_string().yellow()
-    );
+/// Open the picture an image insert refers to.
+fn load_image(
+    source: &ImageSource,
+    frepo: &FormatRepo,
+    attachments: &[Vec<u8>],
+) -> Result<image::RgbaImage, MdlError> {
+    match source {
+        ImageSource::Attachment(index) => attachments::load(attachments, *index),
+        ImageSource::Format(format) => {
+            Ok(image::open(&frepo.resolve(format)?.image_path)?.into_rgba8())
+        }
+    }
+}
 
-    // output PNG vector
-    Ok(png_out)
+/// Scale a picture into a rectangle of the image, as `fit` says.
+fn apply_image(
+    base: &mut image::RgbaImage,
+    picture: &image::RgbaImage,
+    x_left: u32,
+    y_top: u32,
+    width: u32,
+    height: u32,
+    fit: Fit,
+) {
+    use image::imageops::{crop_imm, overlay, resize, FilterType};
+    if width == 0 || height == 0 || picture.width() == 0 || picture.height() == 0 {
+        return;
+    }
+    let scale_x = width as f32 / picture.width() as f32;
+    let scale_y = height as f32 / picture.height() as f32;
+    let scaled = |scale: f32| {
+        let w = ((picture.width() as f32 * scale).round() as u32).max(1);
+        let h = ((picture.height() as f32 * scale).round() as u32).max(1);
+        resize(picture, w, h, FilterType::Triangle)
+    };
+    let fitted = match fit {
+        Fit::Stretch => resize(picture, width, height, FilterType::Triangle),
+        Fit::Contain => scaled(scale_x.min(scale_y)),
+        Fit::Cover => {
+            let covering = scaled(scale_x.max(scale_y));
+            let crop_x = covering.width().saturating_sub(width) / 2;
+            let crop_y = covering.height().saturating_sub(height) / 2;
+            crop_imm(&covering, crop_x, crop_y, width, height).to_image()
+        }
+    };
+    // center whatever doesn't fill the rectangle exactly
+    let x = x_left + width.saturating_sub(fitted.width()) / 2;
+    let y = y_top + height.saturating_sub(fitted.height()) / 2;
+    overlay(base, &fitted, x, y);
 }
 
 const PRINT_TIME_CAPTION_APPLICATION: bool = false;
 fn print_time(start_time: &mut (Instant, Instant), text: &str) {
     if PRINT_TIME_CAPTION_APPLICATION {
-        println!(
+        eprintln!(
             "{}: {} ms. Total {} ms.",
             text,
             start_time.0.elapsed().as_millis().to_string().yellow(),
-            start_time.1.elapsed().as_millis().to_string()
+            start_time.1.elapsed().as_millis()
+        );
+        start_time.0 = Instant::now();
+    }
+}
+
+/// How to draw a caption, with every default filled in.
+struct TextLook {
+    fonts: &'static FontRegistry,
+    /// indices of the fonts to use, in order of preference
+    font_chain: Vec<usize>,
+    color: [u8; 4],
+    outline: [u8; 4],
+    /// `None` scales the outline with the image
+    outline_width: Option<u32>,
+    /// `None` lets the text fill the caption area
+    max_size: Option<f32>,
+    align: HorizontalAlign,
+    valign: VerticalAlign,
+    uppercase: bool,
+}
+
+/// Fill in the defaults for a style: classic white default font with a black
+/// outline, centered, aligned to `valign` unless the style says otherwise.
+fn text_look(style: &CaptionStyle, valign: VAlign) -> Result<TextLook, MdlError> {
+    let fonts = FontRegistry::global()?;
+    Ok(TextLook {
+        fonts,
+        font_chain: fonts.chain(style.font.as_deref())?,
+        color: style.color.map_or([255, 255, 255, 255], |c| c.0),
+        outline: style.outline.map_or([0, 0, 0, 255], |c| c.0),
+        outline_width: style.outline_width,
+        max_size: style.size,
+        align: match style.align {
+            Some(Align::Left) => HorizontalAlign::Left,
+            Some(Align::Right) => HorizontalAlign::Right,
+            Some(Align::Center) | None => HorizontalAlign::Center,
+        },
+        valign: match style.valign.unwrap_or(valign) {
+            VAlign::Top => VerticalAlign::Top,
+            VAlign::Middle => VerticalAlign::Middle,
+            VAlign::Bottom => VerticalAlign::Bottom,
+        },
+        uppercase: style.uppercase.unwrap_or(false),
+    })
+}
+
+impl TextLook {
+    fn cased(&self, text: &str) -> String {
+        if self.uppercase {
+            text.to_uppercase()
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// A bar of text above or below the picture, as tall as its text needs.
+struct Bar {
+    text: String,
+    look: TextLook,
+    /// height of the text itself, without padding
+    text_height: u32,
+    height: u32,
+}
+
+impl Bar {
+    /// Text at its style's size, a twelfth of the width by default and at
+    /// most half of it, wrapped to the width, with half a line of padding
+    /// above and below.
+    fn new(text: &str, mut look: TextLook, width: u32) -> Bar {
+        let size = look
+            .max_size
+            .unwrap_or(width as f32 / 12.0)
+            .min(width as f32 / 2.0)
+            .max(6.0);
+        look.max_size = Some(size);
+        let mut layout = Layout::<Option<usize>>::new(CoordinateSystem::PositiveYDown);
+        layout.reset(&LayoutSettings {
+            x: 0.0,
+            y: 0.0,
+            max_width: Some(width.saturating_sub(40) as f32),
+            max_height: None,
+            horizontal_align: look.align,
+            vertical_align: VerticalAlign::Top,
+            wrap_style: WrapStyle::Word,
+            wrap_hard_breaks: true,
+        });
+        lay_out(
+            &mut layout,
+            &emoji::segments(&look.cased(text)),
+            size,
+            &look,
         );
-        (*start_time).0 = Instant::now();
+        // apply_caption starts at 80% of the height it's given
+        let text_height = layout.height().max(size / 0.8).ceil() as u32;
+        Bar {
+            text: text.to_string(),
+            look,
+            text_height,
+            height: text_height + size as u32,
+        }
+    }
+
+    /// Draw the bar's text on `image`, with the top of the bar at `y_top`.
+    fn draw(&self, image: image::RgbaImage, y_top: u32) -> image::RgbaImage {
+        let width = image.width().saturating_sub(40);
+        let padding = (self.height - self.text_height) / 2;
+        apply_caption(
+            image,
+            &self.text,
+            20,
+            y_top + padding,
+            width,
+            self.text_height,
+            &self.look,
+        )
+    }
+}
+
+/// Lay out a caption at one font size. Glyphs which stand in for an emoji
+/// carry its index in `segments`.
+fn lay_out(layout: &mut Layout<Option<usize>>, segments: &[Segment], size: f32, look: &TextLook) {
+    layout.clear();
+    for (i, segment) in segments.iter().enumerate() {
+        match segment {
+            Segment::Text(text) => {
+                for (run, font_index) in look.fonts.runs(text, &look.font_chain) {
+                    let style = TextStyle::with_user_data(run, size, font_index, None);
+                    layout.append(&look.fonts.fonts, &style);
+                }
+            }
+            // reserve room for the emoji with a wide letter
+            Segment::Emoji(_) => {
+                let style = TextStyle::with_user_data("M", size, look.font_chain[0], Some(i));
+                layout.append(&look.fonts.fonts, &style);
+            }
+        }
     }
 }
 
@@ -149,17 +485,16 @@ fn apply_caption(
     y_top: u32,
     width: u32,
     height: u32,
-    vert_align: VerticalAlign,
+    look: &TextLook,
 ) -> image::RgbaImage {
-    use image::DynamicImage::*;
-
     let mut start_time = (Instant::now(), Instant::now());
     print_time(&mut start_time, "start");
 
-    let mut capt_img: image::GrayAlphaImage =
-        image::ImageBuffer::from_pixel(base.width(), base.height(), image::LumaA([0, 0]));
+    let mut coverage: image::GrayImage =
+        image::ImageBuffer::from_pixel(base.width(), base.height(), image::Luma([0]));
 
-    let mut layout = Layout::<()>::new(CoordinateSystem::PositiveYDown);
+    // glyphs which stand in for an emoji carry its index in `segments`
+    let mut layout = Layout::<Option<usize>>::new(CoordinateSystem::PositiveYDown);
 
     print_time(&mut start_time, "layout");
 
@@ -169,21 +504,23 @@ fn apply_caption(
         y: y_top as f32,
         max_width: Some(width as f32),
         max_height: Some(height as f32),
-        horizontal_align: HorizontalAlign::Center,
-        vertical_align: vert_align,
+        horizontal_align: look.align,
+        vertical_align: look.valign,
         wrap_style: WrapStyle::Word,
         wrap_hard_breaks: true,
     });
 
     // loop until the text fits
-    let impfont: &Font = &IMPACT_FONT;
+    let caption = look.cased(caption);
+    let segments = emoji::segments(&caption);
     let mut size: f32 = height as f32 * 0.8;
+    if let Some(max_size) = look.max_size {
+        size = size.min(max_size);
+    }
     while size > 6.0 {
-        layout.clear();
-        //println!("{:#?}", caption.as_bytes());
-        layout.append(&[impfont], &TextStyle::new(caption, size, 0));
+        lay_out(&mut layout, &segments, size, look);
         //println!("{}, {}", height, layout.height());
-        if layout.height() <= height as f32 && !(layout.lines() > caption.matches(' ').count() + 1)
+        if layout.height() <= height as f32 && layout.lines() <= caption.matches(' ').count() + 1
         {
             break;
         }
@@ -192,10 +529,16 @@ fn apply_caption(
 
     print_time(&mut start_time, "loop till fits");
 
-    // draw each glyph onto the capt_img
+    // draw each glyph's coverage
+    let mut emoji_glyphs = Vec::new();
     for glyph in layout.glyphs() {
         //println!("{:#?}", glyph);
-        let (metrics, bitmap) = IMPACT_FONT.rasterize_config(glyph.key);
+        if glyph.user_data.is_some() {
+            emoji_glyphs.push(glyph);
+            continue;
+        }
+        let font = &look.fonts.fonts[glyph.key.font_index];
+        let (metrics, bitmap) = font.rasterize_config(glyph.key);
         let height = metrics.height;
         let width = metrics.width;
         for j in 0..height {
@@ -208,11 +551,8 @@ fn apply_caption(
                 {
                     continue;
                 }
-                let mut pixel = capt_img.get_pixel_mut(image_x, image_y);
+                let pixel = coverage.get_pixel_mut(image_x, image_y);
                 pixel.0[0] = std::cmp::max(pixel.0[0], *v);
-                if *v > 0 {
-                    pixel.0[1] = 255;
-                }
             }
         }
     }
@@ -220,38 +560,80 @@ fn apply_caption(
     print_time(&mut start_time, "draw glyphs");
 
     // add border around letters
-    let mut transparency_plane =
-        ImageBuffer::from_fn(capt_img.width(), capt_img.height(), |x, y| {
-            return image::Luma([capt_img.get_pixel(x, y).0[1]]);
-        });
-    print_time(&mut start_time, "extract plane");
-    imageproc::morphology::dilate_mut(
-        &mut transparency_plane,
-        imageproc::distance_transform::Norm::LInf,
-        std::convert::TryInto::try_into((capt_img.height() + capt_img.width()) / (2 * 150))
-            .unwrap(),
-    );
-    print_time(&mut start_time, "dialating");
-    //let transparency_plane = imageproc::filter::gaussian_blur_f32(&transparency_plane, 1.2);
-    let transparency_plane = imageproc::filter::box_filter(&transparency_plane, 1, 1);
+    let outline_width = look
+        .outline_width
+        .unwrap_or((base.height() + base.width()) / (2 * 150))
+        .min(u8::MAX as u32) as u8;
+    let outline_plane = if outline_width > 0 && look.outline[3] > 0 {
+        let mut outline_plane =
+            ImageBuffer::from_fn(coverage.width(), coverage.height(), |x, y| {
+                image::Luma([if coverage.get_pixel(x, y).0[0] > 0 {
+                    255
+                } else {
+                    0
+                }])
+            });
+        print_time(&mut start_time, "extract plane");
+        imageproc::morphology::dilate_mut(
+            &mut outline_plane,
+            imageproc::distance_transform::Norm::LInf,
+            outline_width,
+        );
+        print_time(&mut start_time, "dialating");
+        //let outline_plane = imageproc::filter::gaussian_blur_f32(&outline_plane, 1.2);
+        Some(imageproc::filter::box_filter(&outline_plane, 1, 1))
+    } else {
+        None
+    };
     print_time(&mut start_time, "blurring");
-    for (_, r) in capt_img.enumerate_rows_mut() {
-        for (x, y, p) in r {
-            p.0[1] = transparency_plane.get_pixel(x, y).0[0];
+
+    // color the letters over their outline
+    let mut capt_img = image::RgbaImage::from_fn(base.width(), base.height(), |x, y| {
+        let text_alpha =
+            coverage.get_pixel(x, y).0[0] as f32 / 255.0 * look.color[3] as f32 / 255.0;
+        let outline_alpha = outline_plane
+            .as_ref()
+            .map_or(0.0, |p| p.get_pixel(x, y).0[0] as f32 / 255.0)
+            * look.outline[3] as f32
+            / 255.0
+            * (1.0 - text_alpha);
+        let alpha = text_alpha + outline_alpha;
+        if alpha <= 0.0 {
+            return image::Rgba([0, 0, 0, 0]);
         }
-    }
+        let mut pixel = [0, 0, 0, (alpha * 255.0).round() as u8];
+        let channels = pixel.iter_mut().zip(&look.color).zip(&look.outline);
+        for ((channel, text), outline) in channels.take(3) {
+            *channel = ((*text as f32 * text_alpha + *outline as f32 * outline_alpha) / alpha)
+                .round() as u8;
+        }
+        image::Rgba(pixel)
+    });
 
     print_time(&mut start_time, "restore plane");
 
+    // put the emoji where their stand-ins are, about as tall as the text
+    for glyph in emoji_glyphs {
+        let sprite = match &segments[glyph.user_data.unwrap()] {
+            Segment::Emoji(sprite) => sprite,
+            Segment::Text(_) => continue,
+        };
+        let side = std::cmp::max(1, (glyph.height as f32 * 1.25).round() as u32);
+        let sprite =
+            image::imageops::resize(sprite, side, side, image::imageops::FilterType::Triangle);
+        let center_x = glyph.x + glyph.width as f32 / 2.0;
+        let baseline = glyph.y + glyph.height as f32;
+        let x = (center_x - side as f32 / 2.0).max(0.0) as u32;
+        let y = (baseline - side as f32 * 0.9).max(0.0) as u32;
+        image::imageops::overlay(&mut capt_img, &sprite, x, y);
+    }
+
+    print_time(&mut start_time, "draw emoji");
+
     // overlay capt_img over the base
-    image::imageops::overlay(
-        &mut base,
-        &image::DynamicImage::into_rgba8(ImageLumaA8(capt_img)),
-        0,
-        0,
-    );
+    image::imageops::overlay(&mut base, &capt_img, 0, 0);
 
     print_time(&mut start_time, "overlay");
 
-    return base;
+    base
 }
@@ -3,11 +3,19 @@
 use colored::*;
 use std::collections::HashMap;
 use std::ffi::OsStr;
+use std::fmt;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
 use walkdir::WalkDir;
 
+use crate::animation::FrameRange;
+use crate::error::MdlError;
+use crate::metadata::FormatMetadata;
+use crate::search::SearchIndex;
+use crate::style::CaptionStyle;
+use crate::suggest::{suggest, MAX_SUGGESTIONS};
+
 type FormatMap = HashMap<String, MemeFormat>;
 type InsertsMap = HashMap<String, ((u32, u32), (u32, u32))>;
 
@@ -16,6 +24,49 @@ pub struct FormatRepo {
     pub name: String,
     /// map containing all the memes by their fully qualified memeIDs
     pub formats: FormatMap,
+    /// directory the repo was loaded from
+    pub root_path: PathBuf,
+    /// files which were skipped, and why
+    pub diagnostics: Vec<LoadDiagnostic>,
+    /// files which loaded, but look wrong
+    pub warnings: Vec<LoadDiagnostic>,
+    /// search terms for every format
+    search_index: SearchIndex,
+}
+
+/// What differs between two loads of the same repo.
+pub struct RepoChanges {
+    /// memeIDs which are new
+    pub added: Vec<String>,
+    /// memeIDs which are gone
+    pub removed: Vec<String>,
+    /// files which were skipped, and why
+    pub skipped: Vec<LoadDiagnostic>,
+}
+
+/// A file in the repo which couldn't be loaded. The rest of the repo loads
+/// without it.
+#[derive(Clone, Debug)]
+pub struct LoadDiagnostic {
+    /// path of the file, relative to the repo root
+    pub path: PathBuf,
+    /// what's wrong with it
+    pub problem: String,
+}
+
+impl LoadDiagnostic {
+    fn new(root_path: &Path, path: &Path, problem: String) -> LoadDiagnostic {
+        LoadDiagnostic {
+            path: path.strip_prefix(root_path).unwrap_or(path).to_path_buf(),
+            problem,
+        }
+    }
+}
+
+impl fmt::Display for LoadDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.problem)
+    }
 }
 
 pub struct MemeFormat {
@@ -25,19 +76,53 @@ pub struct MemeFormat {
     pub image_path: PathBuf,
     /// inserts and their coordinates
     pub inserts: InsertsMap,
+    /// alternative names which resolve to this format
+    pub aliases: Vec<String>,
+    /// human-readable explanation of the format
+    pub description: Option<String>,
+    /// broad categories, e.g. "comparison"
+    pub tags: Vec<String>,
+    /// extra words people might search for
+    pub keywords: Vec<String>,
+    /// human-readable name
+    pub name: Option<String>,
+    /// who made the template
+    pub author: Option<String>,
+    /// URL the image came from
+    pub source: Option<String>,
+    /// license the image is used under
+    pub license: Option<String>,
+    /// style for all text on this format
+    pub style: CaptionStyle,
+    /// style for the text of particular inserts, layered over `style`
+    pub insert_styles: HashMap<String, CaptionStyle>,
+    /// for animations, the frames to draw on, unless the MDL says otherwise
+    pub frames: Option<FrameRange>,
 }
 
 const PRINT_REPO_DEBUG: bool = true;
 
 impl FormatRepo {
+    /// Load every format under `root_path`. Files which can't be loaded are
+    /// skipped and listed in `diagnostics`; only a missing root is an error.
     pub fn new(root_path: PathBuf, name: String) -> Result<FormatRepo, io::Error> {
         let mut formats_map = FormatMap::new();
+        let mut diagnostics = Vec::new();
+        let mut warnings = Vec::new();
+        let mut metadata_paths = Vec::new();
 
         // check assertions
-        assert!(root_path.exists(), "Format repo root path does not exist.");
-        assert!(root_path.is_dir(), "Format repo root path not a directory.");
+        if !root_path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "Format repo root {} is not a directory.",
+                    root_path.display()
+                ),
+            ));
+        }
         if PRINT_REPO_DEBUG {
-            println!(
+            eprintln!(
                 "  {:41.41}  {:52.52}  {:20.20}",
                 "IDENTIFIER", "IMAGE PATH", "METADATA"
             );
@@ -49,104 +134,368 @@ impl FormatRepo {
             .sort_by(|a, b| a.file_name().cmp(b.file_name()))
             .into_iter()
         {
-            let e = entry.unwrap();
+            let e = match entry {
+                Ok(e) => e,
+                Err(err) => {
+                    let path = err.path().unwrap_or(&root_path).to_path_buf();
+                    diagnostics.push(LoadDiagnostic::new(&root_path, &path, err.to_string()));
+                    continue;
+                }
+            };
+            let problem = |problem: String| LoadDiagnostic::new(&root_path, e.path(), problem);
 
             // only process files, not directories
             if !e.file_type().is_file() {
                 continue;
             }
-            // don't process metadata files
+            // metadata files are read along with their image
             if e.path().extension().unwrap_or(OsStr::new("")).eq("meme") {
+                metadata_paths.push(e.path().to_path_buf());
                 continue;
             }
 
-            // get the path into a vector
-            let mut path_vec: Vec<&str> = e.path().iter().map(|s| s.to_str().unwrap()).collect();
-            path_vec[0] = &name; // make the first part say "Meme"
-            let last_index = path_vec.len() - 1; // remove the file extension
-            let last_elem = path_vec.last_mut().unwrap();
-            path_vec[last_index] = &mut &last_elem[..last_elem.find('.').unwrap()];
+            // make sure it's an image we can read
+            if image::ImageFormat::from_path(e.path()).is_err() {
+                diagnostics.push(problem("Not an image file.".to_string()));
+                continue;
+            }
+            if let Err(err) = image::image_dimensions(e.path()) {
+                diagnostics.push(problem(format!("Unreadable image: {}", err)));
+                continue;
+            }
 
-            // check if we have metadata available
-            let metadata = get_metadata(&e.path());
-            let inserts = metadata.clone().unwrap_or(InsertsMap::new());
+            // get the path (relative to the repo root) into a vector
+            let mut path_vec: Vec<&str> = vec![&name]; // make the first part say "Meme"
+            let relative_path = e.path().strip_prefix(&root_path).unwrap_or(e.path());
+            match relative_path
+                .iter()
+                .map(|s| s.to_str())
+                .collect::<Option<Vec<&str>>>()
+            {
+                Some(parts) => path_vec.extend(parts),
+                None => {
+                    diagnostics.push(problem("Path is not valid UTF-8.".to_string()));
+                    continue;
+                }
+            }
+            let last_elem = path_vec.pop().unwrap(); // remove the file extension
+            path_vec.push(&last_elem[..last_elem.find('.').unwrap_or(last_elem.len())]);
 
             // form the string
             let memeid = path_vec.join(".");
+            if let Some(other) = formats_map.get(&memeid) {
+                diagnostics.push(problem(format!(
+                    "Meme ID {} is already used by {}.",
+                    memeid,
+                    other.image_path.display()
+                )));
+                continue;
+            }
+
+            // check if we have metadata available
+            let metadata = get_metadata(e.path());
             if PRINT_REPO_DEBUG {
-                println!(
+                eprintln!(
                     ") {:41.41}  {:52.52}  {:20.20}",
                     memeid,
                     e.path().display(),
                     match &metadata {
-                        Err(e) => e.to_string(),
-                        Ok(ins) => format!("{} inserts", ins.len()),
+                        Err(_) => "Skipped".to_string(),
+                        Ok(None) => "None".to_string(),
+                        Ok(Some(m)) => format!("{} inserts", m.inserts.len()),
                     }
                 );
             }
+            let metadata_path = e.path().with_extension("meme");
+            let meta = match metadata {
+                Ok(meta) => meta.unwrap_or_default(),
+                Err(err) => {
+                    diagnostics.push(LoadDiagnostic::new(&root_path, &metadata_path, err));
+                    continue;
+                }
+            };
+            for field in meta.unknown_fields() {
+                warnings.push(LoadDiagnostic::new(
+                    &root_path,
+                    &metadata_path,
+                    format!("Unknown field {} is ignored.", field),
+                ));
+            }
+
+            // inserts are drawn by subtracting their corners, which must
+            // be the right way round
+            let inverted = meta.inserts.iter().find(|(_, insert)| {
+                let [[x1, y1], [x2, y2]] = insert.coords;
+                x2 <= x1 || y2 <= y1
+            });
+            if let Some((insert_name, insert)) = inverted {
+                let [[x1, y1], [x2, y2]] = insert.coords;
+                diagnostics.push(LoadDiagnostic::new(
+                    &root_path,
+                    &metadata_path,
+                    format!(
+                        "Insert \"{}\" has no area: the second corner ({}, {}) must be right \
+                        of and below the first ({}, {}).",
+                        insert_name, x2, y2, x1, y1
+                    ),
+                ));
+                continue;
+            }
 
             // add to the hashmap
+            let mut inserts = InsertsMap::new();
+            let mut insert_styles = HashMap::new();
+            for (insert_name, insert) in meta.inserts {
+                let [[x1, y1], [x2, y2]] = insert.coords;
+                inserts.insert(insert_name.clone(), ((x1, y1), (x2, y2)));
+                insert_styles.insert(insert_name, insert.style);
+            }
             formats_map.insert(
                 memeid.clone(),
                 MemeFormat {
                     memeid,
                     image_path: e.path().to_path_buf(),
                     inserts,
+                    aliases: meta.aliases,
+                    description: meta.description,
+                    tags: meta.tags,
+                    keywords: meta.keywords,
+                    name: meta.name,
+                    author: meta.author,
+                    source: meta.source,
+                    license: meta.license,
+                    style: meta.style.style,
+                    insert_styles,
+                    frames: meta.frames,
                 },
             );
         }
 
+        // metadata is only useful next to an image
+        for metadata_path in metadata_paths {
+            let has_image = formats_map
+                .values()
+                .any(|f| f.image_path.with_extension("meme") == metadata_path);
+            let was_reported = diagnostics
+                .iter()
+                .any(|d| root_path.join(&d.path) == metadata_path);
+            if !has_image && !was_reported {
+                diagnostics.push(LoadDiagnostic::new(
+                    &root_path,
+                    &metadata_path,
+                    "Metadata file has no image.".to_string(),
+                ));
+            }
+        }
+
+        diagnostics.sort_by(|a, b| a.path.cmp(&b.path));
+
         // gloat
-        println!(
+        eprintln!(
             "Repository loaded with {} memes.",
             formats_map.len().to_string().bold()
         );
+        if !diagnostics.is_empty() {
+            eprintln!(
+                "Skipped {} files:",
+                diagnostics.len().to_string().yellow().bold()
+            );
+            for diagnostic in &diagnostics {
+                eprintln!("  {}", diagnostic);
+            }
+        }
+        for warning in &warnings {
+            eprintln!("{} {}", "Warning:".yellow(), warning);
+        }
 
         // create and return the struct
+        let search_index = SearchIndex::build(formats_map.values());
         Ok(FormatRepo {
             name,
             formats: formats_map,
+            root_path,
+            diagnostics,
+            warnings,
+            search_index,
+        })
+    }
+
+    /// Load this repo again from disk, picking up added, changed, or removed
+    /// formats. The current repo is left as it is.
+    pub fn reload(&self) -> Result<FormatRepo, io::Error> {
+        FormatRepo::new(self.root_path.clone(), self.name.clone())
+    }
+
+    /// Compare this repo to an older load of it.
+    pub fn changes_since(&self, old: &FormatRepo) -> RepoChanges {
+        let mut added: Vec<String> = self
+            .formats
+            .keys()
+            .filter(|k| !old.formats.contains_key(*k))
+            .cloned()
+            .collect();
+        let mut removed: Vec<String> = old
+            .formats
+            .keys()
+            .filter(|k| !self.formats.contains_key(*k))
+            .cloned()
+            .collect();
+        added.sort();
+        removed.sort();
+        RepoChanges {
+            added,
+            removed,
+            skipped: self.diagnostics.clone(),
+        }
+    }
+
+    /// Find the formats best matching a free-text query, best first.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<&MemeFormat> {
+        self.search_index
+            .search(query, limit)
+            .iter()
+            .filter_map(|memeid| self.formats.get(memeid))
+            .collect()
+    }
+
+    /// Look up a format by its fully qualified memeID, or failing that by
+    /// something shorter. In order of preference:
+    /// 1. the memeID, ignoring case (`meme.drakeyesno`)
+    /// 2. an alias from the `.meme` file, ignoring case (`drake`)
+    /// 3. trailing segments of the memeID, ignoring case (`WhatIfIToldYou`,
+    ///    `Matrix.WhatIfIToldYou`)
+    ///
+    /// If the best matching rule finds several formats, that's an error.
+    pub fn resolve(&self, query: &str) -> Result<&MemeFormat, MdlError> {
+        if let Some(format) = self.formats.get(query) {
+            return Ok(format);
+        }
+        let query_lower = query.to_lowercase();
+        let suffix = format!(".{}", query_lower);
+        let rules: [&dyn Fn(&MemeFormat) -> bool; 3] = [
+            &|f| f.memeid.to_lowercase() == query_lower,
+            &|f| f.aliases.iter().any(|a| a.to_lowercase() == query_lower),
+            &|f| f.memeid.to_lowercase().ends_with(&suffix),
+        ];
+        for rule in rules.iter() {
+            let mut matches: Vec<&MemeFormat> = self.formats.values().filter(|f| rule(f)).collect();
+            match matches.len() {
+                0 => continue,
+                1 => return Ok(matches[0]),
+                _ => {
+                    matches.sort_by(|a, b| a.memeid.cmp(&b.memeid));
+                    return Err(MdlError::AmbiguousFormat {
+                        format: query.to_string(),
+                        candidates: matches.iter().map(|f| f.memeid.clone()).collect(),
+                    });
+                }
+            }
+        }
+        Err(MdlError::UnknownFormat {
+            format: query.to_string(),
+            suggestions: suggest(query, self.formats.keys(), MAX_SUGGESTIONS),
         })
     }
 }
 
-fn get_metadata(meme_image_path: &Path) -> Result<InsertsMap, &str> {
+/// Read the `.meme` file next to an image, if there is one.
+fn get_metadata(meme_image_path: &Path) -> Result<Option<FormatMetadata>, String> {
     let metadata_path = meme_image_path.with_extension("meme");
     if !metadata_path.exists() {
-        return Err("None");
+        return Ok(None);
     };
-    let json: serde_json::Value = serde_json::from_reader(std::io::BufReader::new(
-        std::fs::File::open(&metadata_path).unwrap(),
-    ))
-    .expect(&format!("Failed to parse meme metadata JSON {:?}.", metadata_path).to_string());
-    let inserts_json = json
-        .get("inserts")
-        .ok_or_else(|| "No inserts")?
-        .as_object()
-        .ok_or_else(|| "Inserts not object")?;
-    let mut inserts = InsertsMap::new();
-    for (ins_name, ins_val) in inserts_json {
-        let coords_arr = ins_val
-            .get("coords")
-            .ok_or("No coords")?
-            .as_array()
-            .ok_or("Coords not array")?;
-        if coords_arr.len() != 2 {
-            return Err("Not coords pair");
-        }
-        let cs1: Vec<u32> = coords_arr[0]
-            .as_array()
-            .ok_or("Coords[0] not array")?
-            .into_iter()
-            .map(|c| c.as_u64().unwrap_or(0) as u32)
-            .collect();
-        let cs2: Vec<u32> = coords_arr[1]
-            .as_array()
-            .ok_or("Coords[1] not array")?
-            .into_iter()
-            .map(|c| c.as_u64().unwrap_or(0) as u32)
-            .collect();
-        inserts.insert(ins_name.clone(), ((cs1[0], cs1[1]), (cs2[0], cs2[1])));
+    let text = std::fs::read_to_string(&metadata_path)
+        .map_err(|e| format!("Unreadable metadata: {}", e))?;
+    FormatMetadata::parse(&text)
+        .map(Some)
+        .map_err(|e| format!("Malformed metadata: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+    use std::fs;
+
+    /// A fresh, empty directory for a test repo.
+    fn repo_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mdl_chef_{}_{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Add a 100x100 image to the repo in `dir`.
+    fn image(dir: &Path, file: &str) {
+        RgbaImage::new(100, 100).save(dir.join(file)).unwrap();
+    }
+
+    fn problems(diagnostics: &[LoadDiagnostic]) -> Vec<(&str, &str)> {
+        diagnostics
+            .iter()
+            .map(|d| (d.path.to_str().unwrap(), d.problem.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn bad_files_are_skipped_and_reported() {
+        let dir = repo_dir("repo");
+        image(&dir, "Good.png");
+        fs::write(
+            dir.join("Good.meme"),
+            "{inserts: {a: {coords: [[0, 0], [50, 50]]}}, colour: 'red'}",
+        )
+        .unwrap();
+        image(&dir, "Malformed.png");
+        fs::write(dir.join("Malformed.meme"), "{inserts: ").unwrap();
+        image(&dir, "Dup.jpg");
+        image(&dir, "Dup.png");
+        image(&dir, "Inverted.png");
+        fs::write(
+            dir.join("Inverted.meme"),
+            "{inserts: {a: {coords: [[50, 50], [0, 0]]}}}",
+        )
+        .unwrap();
+        fs::write(dir.join("Orphan.meme"), "{inserts: {}}").unwrap();
+        fs::write(dir.join("notes.txt"), "not a meme").unwrap();
+
+        let frepo = FormatRepo::new(dir.clone(), "Meme".to_string()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut ids: Vec<&String> = frepo.formats.keys().collect();
+        ids.sort();
+        assert_eq!(ids, vec!["Meme.Dup", "Meme.Good"]);
+        assert!(frepo.formats["Meme.Dup"].image_path.ends_with("Dup.jpg"));
+
+        let diagnostics = problems(&frepo.diagnostics);
+        let paths: Vec<&str> = diagnostics.iter().map(|(path, _)| *path).collect();
+        assert_eq!(
+            paths,
+            vec![
+                "Dup.png",
+                "Inverted.meme",
+                "Malformed.meme",
+                "Orphan.meme",
+                "notes.txt"
+            ]
+        );
+        assert!(diagnostics[0]
+            .1
+            .starts_with("Meme ID Meme.Dup is already used by"));
+        assert!(diagnostics[1].1.starts_with("Insert \"a\" has no area"));
+        assert!(diagnostics[2].1.starts_with("Malformed metadata:"));
+        assert_eq!(diagnostics[3].1, "Metadata file has no image.");
+        assert_eq!(diagnostics[4].1, "Not an image file.");
+
+        assert_eq!(
+            problems(&frepo.warnings),
+            vec![("Good.meme", "Unknown field colour is ignored.")]
+        );
+    }
+
+    #[test]
+    fn missing_root_is_an_error() {
+        let dir = std::env::temp_dir().join("mdl_chef_no_such_repo");
+        assert!(FormatRepo::new(dir, "Meme".to_string()).is_err());
     }
-    Ok(inserts)
 }
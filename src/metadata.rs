@@ -0,0 +1,109 @@
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::animation::FrameRange;
+use crate::style::CaptionStyle;
+
+/// Newest `.meme` file version this build understands.
+pub const METADATA_VERSION: u32 = 1;
+
+/// Contents of a `.meme` file, which describes the template image next to it.
+/// Written in JSON5 (so plain JSON works too).
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatMetadata {
+    /// version of the `.meme` file format, 1 if missing
+    #[serde(default = "default_version")]
+    pub version: u32,
+    /// human-readable name, e.g. "Drake Yes/No"
+    pub name: Option<String>,
+    /// human-readable explanation of the format
+    pub description: Option<String>,
+    /// who made the template
+    pub author: Option<String>,
+    /// URL the image came from
+    pub source: Option<String>,
+    /// license the image is used under
+    pub license: Option<String>,
+    /// alternative names which resolve to this format
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// broad categories, e.g. "comparison"
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// extra words people might search for
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    /// style for all text on this format, unless an insert says otherwise
+    #[serde(default)]
+    pub style: StyleMetadata,
+    /// rectangles which can be filled in, by name
+    pub inserts: HashMap<String, InsertMetadata>,
+    /// for animations, the frames to draw text and pictures on
+    pub frames: Option<FrameRange>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde_json::Value>,
+}
+
+/// A caption style, as written in a `.meme` file.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct StyleMetadata {
+    #[serde(flatten)]
+    pub style: CaptionStyle,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde_json::Value>,
+}
+
+/// One insert of a `.meme` file: where it is, and optionally how its text
+/// looks, e.g. `{ coords: [[10, 10], [200, 100]], color: "black" }`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct InsertMetadata {
+    /// top left and bottom right corners
+    pub coords: [[u32; 2]; 2],
+    #[serde(flatten)]
+    pub style: CaptionStyle,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde_json::Value>,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+impl FormatMetadata {
+    /// Parse and check the text of a `.meme` file.
+    pub fn parse(text: &str) -> Result<FormatMetadata, String> {
+        let metadata: FormatMetadata = json5::from_str(text).map_err(|e| match e {
+            json5::Error::Message {
+                msg,
+                location: Some(l),
+            } => format!("line {}, column {}: {}", l.line, l.column, msg),
+            json5::Error::Message { msg, .. } => msg,
+        })?;
+        if metadata.version == 0 || metadata.version > METADATA_VERSION {
+            return Err(format!(
+                "version {} is not supported, the newest is {}.",
+                metadata.version, METADATA_VERSION
+            ));
+        }
+        Ok(metadata)
+    }
+
+    /// Fields which were in the file but mean nothing to us, probably typos.
+    /// Dotted paths like `inserts.bad.colour`.
+    pub fn unknown_fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = self.unknown.keys().cloned().collect();
+        fields.extend(self.style.unknown.keys().map(|k| format!("style.{}", k)));
+        let mut insert_names: Vec<&String> = self.inserts.keys().collect();
+        insert_names.sort();
+        for name in insert_names {
+            fields.extend(
+                self.inserts[name]
+                    .unknown
+                    .keys()
+                    .map(|k| format!("inserts.{}.{}", name, k)),
+            );
+        }
+        fields
+    }
+}
@@ -0,0 +1,201 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, EncodableLayout, Frame, ImageEncoder, RgbaImage};
+use std::collections::HashMap;
+
+use crate::animation;
+use crate::error::MdlError;
+use crate::mdl::{MdlOutput, OutputFormat};
+use crate::meme_generator::DrawnMeme;
+
+/// Discord's upload limit without boosts, used unless `upload_limit_mb` says otherwise.
+const DEFAULT_UPLOAD_LIMIT_MB: f64 = 8.0;
+
+const DEFAULT_QUALITY: u8 = 85;
+
+/// Lossy encodings are recompressed down to this quality before shrinking
+/// the image instead.
+const MIN_QUALITY: u8 = 50;
+
+/// How many times to recompress or shrink an image which doesn't fit the
+/// upload limit before giving up.
+const MAX_SHRINK_ATTEMPTS: usize = 6;
+
+/// A finished meme, encoded.
+pub struct RenderedMeme {
+    pub data: Vec<u8>,
+    /// file extension for the encoding, like `png`
+    pub extension: &'static str,
+    /// MIME type of the encoding, like `image/png`
+    pub mime: &'static str,
+}
+
+/// How to encode memes: the MDL's `output`, with the server's defaults for
+/// anything it leaves out.
+///
+/// The defaults come from the settings:
+/// - `output_format`: `png` (the default), `jpeg`, `webp`, or `gif`
+/// - `output_quality`: 1 to 100, for JPEG and WebP. 85 by default.
+/// - `output_max_width`, `output_max_height`: larger memes are scaled down
+/// - `upload_limit_mb`: largest file to produce, 8 by default. Bigger files
+///   are recompressed at a lower quality, or scaled down, until they fit.
+struct OutputSettings {
+    format: Option<OutputFormat>,
+    quality: u8,
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    upload_limit: usize,
+}
+
+impl OutputSettings {
+    fn new(output: &MdlOutput) -> Result<OutputSettings, MdlError> {
+        let empty = HashMap::new();
+        let settings = crate::SETTINGS.get().unwrap_or(&empty);
+        let upload_limit_mb =
+            setting(settings, "upload_limit_mb")?.unwrap_or(DEFAULT_UPLOAD_LIMIT_MB);
+        Ok(OutputSettings {
+            format: output.format.or(setting(settings, "output_format")?),
+            quality: output
+                .quality
+                .or(setting(settings, "output_quality")?)
+                .unwrap_or(DEFAULT_QUALITY)
+                .clamp(1, 100),
+            max_width: output
+                .max_width
+                .or(max_size_setting(settings, "output_max_width")?),
+            max_height: output
+                .max_height
+                .or(max_size_setting(settings, "output_max_height")?),
+            upload_limit: (upload_limit_mb * 1024.0 * 1024.0) as usize,
+        })
+    }
+}
+
+fn setting<T: std::str::FromStr>(
+    settings: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<T>, MdlError> {
+    match settings.get(key) {
+        Some(value) => value.trim().parse().map(Some).map_err(|_| {
+            MdlError::RenderError(format!("Setting {} has a bad value: {}", key, value))
+        }),
+        None => Ok(None),
+    }
+}
+
+fn max_size_setting(
+    settings: &HashMap<String, String>,
+    key: &str,
+) -> Result<Option<u32>, MdlError> {
+    match setting(settings, key)? {
+        Some(0) => Err(MdlError::RenderError(format!(
+            "Setting {} must be at least 1.",
+            key
+        ))),
+        size => Ok(size),
+    }
+}
+
+/// Encode a drawn meme as its `output` says. Animations become GIFs unless
+/// a still format was asked for, which gets the meme's still frame.
+pub fn encode(drawn: DrawnMeme, output: &MdlOutput) -> Result<RenderedMeme, MdlError> {
+    let settings = OutputSettings::new(output)?;
+    let DrawnMeme { mut frames, still } = drawn;
+
+    // fit within the maximum dimensions
+    let (width, height) = frames[0].buffer().dimensions();
+    let scale = [(settings.max_width, width), (settings.max_height, height)]
+        .iter()
+        .filter_map(|(max, actual)| max.map(|max| max as f64 / *actual as f64))
+        .fold(1.0, f64::min);
+    if scale < 1.0 {
+        for frame in &mut frames {
+            let scaled = animation::scaled(frame.buffer(), scale);
+            *frame = Frame::from_parts(scaled, 0, 0, frame.delay());
+        }
+    }
+
+    let format = match settings.format {
+        Some(format) => format,
+        None if frames.len() > 1 => OutputFormat::Gif,
+        None => OutputFormat::Png,
+    };
+    let data = if format == OutputFormat::Gif {
+        animation::encode_gif(&frames, settings.upload_limit)?
+    } else {
+        let image = frames.swap_remove(still).into_buffer();
+        encode_within_limit(&image, format, settings.quality, settings.upload_limit)?
+    };
+    Ok(RenderedMeme {
+        data,
+        extension: format.extension(),
+        mime: format.mime(),
+    })
+}
+
+/// Encode a still image, lowering the quality and then the size until it
+/// fits in `limit` bytes.
+fn encode_within_limit(
+    image: &RgbaImage,
+    format: OutputFormat,
+    mut quality: u8,
+    limit: usize,
+) -> Result<Vec<u8>, MdlError> {
+    let lossy = matches!(format, OutputFormat::Jpeg | OutputFormat::WebP);
+    let mut scale = 1.0;
+    let mut size = 0;
+    for _ in 0..MAX_SHRINK_ATTEMPTS {
+        let data = if scale < 1.0 {
+            encode_still(&animation::scaled(image, scale), format, quality)?
+        } else {
+            encode_still(image, format, quality)?
+        };
+        if data.len() <= limit {
+            return Ok(data);
+        }
+        size = data.len();
+        if lossy && quality > MIN_QUALITY {
+            quality = quality.saturating_sub(20).max(MIN_QUALITY);
+        } else {
+            // the file grows about with the area, aim a little under
+            scale *= (limit as f64 / size as f64).sqrt() * 0.9;
+        }
+    }
+    Err(MdlError::RenderError(format!(
+        "The meme is too big to upload: still {:.1} MB after shrinking it to fit in {:.1} MB.",
+        size as f64 / (1024.0 * 1024.0),
+        limit as f64 / (1024.0 * 1024.0)
+    )))
+}
+
+fn encode_still(image: &RgbaImage, format: OutputFormat, quality: u8) -> Result<Vec<u8>, MdlError> {
+    let mut data = Vec::new();
+    match format {
+        OutputFormat::Png => PngEncoder::new(&mut data).write_image(
+            image.as_bytes(),
+            image.width(),
+            image.height(),
+            ColorType::Rgba8,
+        )?,
+        OutputFormat::Jpeg => {
+            // JPEG has no transparency, so put it on white
+            let flattened = image::RgbImage::from_fn(image.width(), image.height(), |x, y| {
+                let [r, g, b, a] = image.get_pixel(x, y).0;
+                let over_white = |c: u8| (c as u32 * a as u32 / 255 + (255 - a as u32)) as u8;
+                image::Rgb([over_white(r), over_white(g), over_white(b)])
+            });
+            JpegEncoder::new_with_quality(&mut data, quality).encode(
+                flattened.as_bytes(),
+                image.width(),
+                image.height(),
+                ColorType::Rgb8,
+            )?
+        }
+        OutputFormat::WebP => {
+            let encoder = webp::Encoder::from_rgba(image.as_bytes(), image.width(), image.height());
+            data.extend_from_slice(&encoder.encode(quality as f32));
+        }
+        OutputFormat::Gif => unreachable!("GIFs are encoded by animation::encode_gif"),
+    }
+    Ok(data)
+}
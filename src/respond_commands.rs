@@ -1,24 +1,73 @@
-use serenity::{model::interactions::Interaction, prelude::*, utils::MessageBuilder};
+use serenity::{
+    model::application::component::{ActionRowComponent, InputTextStyle},
+    model::application::interaction::{
+        application_command::{ApplicationCommandInteraction, CommandData, CommandDataOption},
+        autocomplete::AutocompleteInteraction,
+        modal::ModalSubmitInteraction,
+        Interaction, InteractionResponseType,
+    },
+    model::channel::AttachmentType,
+    model::id::InteractionId,
+    prelude::*,
+    utils::MessageBuilder,
+};
 
 use colored::*;
 use serde_json::json;
+use std::collections::HashMap;
+
+use mdl_chef::{
+    meme_repository::*,
+    suggest::{complete, suggest, wildcard_match, MAX_SUGGESTIONS},
+    MdlError,
+};
 
-use crate::{mdl::MdlMeme, meme_repository::*};
+use crate::shared_repo::SharedRepo;
+
+/// Discord shows at most this many autocomplete choices.
+const MAX_AUTOCOMPLETE_CHOICES: usize = 25;
 
 // RECEIVING POINT FOR ALL INTERACTIONS
-pub async fn interaction_create(frepo: &FormatRepo, ctx: Context, interaction: Interaction) {
+pub async fn interaction_create(
+    repo: &SharedRepo,
+    settings: &HashMap<String, String>,
+    ctx: Context,
+    interaction: Interaction,
+) {
     //println!("inter {:#?}", interaction);
-    let interaction_data = interaction.data.clone().expect("Interaction had no data");
-    let interaction_name = interaction_data.name.as_str();
-    let interaction_user = match &interaction {
-        Interaction {
-            member: Some(member),
-            ..
-        } => member.display_name().to_string(),
-        Interaction {
-            user: Some(user), ..
-        } => user.name.to_string() + " (in DM)",
-        _ => "nouser".to_string(),
+    let frepo = &repo.get();
+    let interaction = match interaction {
+        Interaction::ApplicationCommand(interaction) => interaction,
+        // only commands with autocomplete options get these. Answer
+        // quietly, they're sent on every keystroke.
+        Interaction::Autocomplete(interaction) => {
+            if let Some((_, completable)) = AUTOCOMPLETE_OPTIONS
+                .iter()
+                .find(|(name, _)| *name == interaction.data.name)
+            {
+                respond_autocomplete(frepo, ctx, interaction, completable).await;
+            }
+            return;
+        }
+        Interaction::ModalSubmit(interaction)
+            if interaction.data.custom_id.starts_with("make:") =>
+        {
+            respond_make_form(frepo, ctx, interaction).await;
+            return;
+        }
+        _ => {
+            println!(
+                "{}... {:#?}",
+                "UNEXPECTED INTERACTION".red().bold(),
+                interaction
+            );
+            return;
+        }
+    };
+    let interaction_name = interaction.data.name.as_str();
+    let interaction_user = match &interaction.member {
+        Some(member) => member.display_name().to_string(),
+        None => interaction.user.name.to_string() + " (in DM)",
     };
     println!(
         "--- {}\nGot interaction {} from user {}.",
@@ -32,9 +81,11 @@ pub async fn interaction_create(frepo: &FormatRepo, ctx: Context, interaction: I
     match interaction_name {
         "help" => respond_help(ctx, interaction).await,
         "credits" => respond_credits(ctx, interaction).await,
-        "searchmemes" => respond_unimpl(ctx, interaction).await,
+        "searchmemes" => respond_searchmemes(frepo, ctx, interaction).await,
         "memeinfo" => respond_memeinfo(frepo, ctx, interaction).await,
         "listmemes" => respond_listmemes(frepo, ctx, interaction).await,
+        "make" => respond_make(frepo, ctx, interaction).await,
+        "reloadmemes" => respond_reloadmemes(repo, settings, ctx, interaction).await,
         _ => println!(
             "{}... {:#?}",
             "UNEXPECTED INTERACTION".red().bold(),
@@ -43,7 +94,50 @@ pub async fn interaction_create(frepo: &FormatRepo, ctx: Context, interaction: I
     }
 }
 
-async fn respond_help(ctx: Context, interaction: Interaction) {
+/// The options of each command which offer autocomplete.
+const AUTOCOMPLETE_OPTIONS: &[(&str, &[&str])] =
+    &[("memeinfo", &["memeid"]), ("make", &["format"])];
+
+/// Offer meme IDs matching the option being typed.
+async fn respond_autocomplete(
+    frepo: &FormatRepo,
+    ctx: Context,
+    interaction: AutocompleteInteraction,
+    completable: &[&str],
+) {
+    let choices = match focused_option(&interaction.data.options) {
+        Some((name, partial)) if completable.contains(&name) => {
+            complete(partial, frepo.formats.keys(), MAX_AUTOCOMPLETE_CHOICES)
+        }
+        _ => Vec::new(),
+    };
+    let choices: Vec<serde_json::Value> = choices
+        .iter()
+        .map(|c| json!({ "name": c, "value": c }))
+        .collect();
+    // answers which miss Discord's 3 second window are refused, which is
+    // fine, another keystroke will come
+    if let Err(e) = ctx
+        .http
+        .create_interaction_response(
+            *interaction.id.as_u64(),
+            &interaction.token,
+            &json!({"type": 8, "data": { "choices": choices }}),
+        )
+        .await
+    {
+        println!("{} {}", "Autocomplete reply failed:".red(), e);
+    }
+}
+
+/// The name and value of the option being typed in, which Discord marks as
+/// `focused`.
+fn focused_option(options: &[CommandDataOption]) -> Option<(&str, &str)> {
+    let option = options.iter().find(|o| o.focused)?;
+    Some((option.name.as_str(), option.value.as_ref()?.as_str()?))
+}
+
+async fn respond_help(ctx: Context, interaction: ApplicationCommandInteraction) {
     let help = MessageBuilder::new()
         .push_bold("MDLChef Bot\n")
         .push("This bot generates memes using MDL, the Meme Description Language.\n")
@@ -63,7 +157,8 @@ async fn respond_help(ctx: Context, interaction: Interaction) {
 ```
 "#)
         .push("Just send a valid MDL snippet in chat and the bot will automatically recognize it and respond. ")
-        .push("It can be either standalone, in a \\`\\`\\` code structure, or surrounded by other text - anything should work.")
+        .push("It can be either standalone, in a \\`\\`\\` code structure, or surrounded by other text - anything should work.\n")
+        .push("Rather fill in a form? Use /make and the bot will show you the MDL for your meme.")
         .build();
     ctx.http
         .create_interaction_response(
@@ -80,7 +175,7 @@ async fn respond_help(ctx: Context, interaction: Interaction) {
         .unwrap();
 }
 
-async fn respond_credits(ctx: Context, interaction: Interaction) {
+async fn respond_credits(ctx: Context, interaction: ApplicationCommandInteraction) {
     let resp = MessageBuilder::new()
         .push_underline_line("Credits")
         .push_line("Thank you to...")
@@ -100,58 +195,444 @@ async fn respond_credits(ctx: Context, interaction: Interaction) {
         .unwrap();
 }
 
-async fn respond_unimpl(ctx: Context, interaction: Interaction) {
-    let resp = MessageBuilder::new()
-        .push_bold(":warning: Error\n")
-        .push("This slash command is not yet implemented.")
-        .build();
+/// Characters of meme IDs per /listmemes page, leaving room for the header.
+const LISTMEMES_PAGE_CHARS: usize = 1800;
+
+/// Longest pattern to repeat back in the reply, so it still fits.
+const LISTMEMES_ECHOED_PATTERN_CHARS: usize = 50;
+
+async fn respond_listmemes(
+    frepo: &FormatRepo,
+    ctx: Context,
+    interaction: ApplicationCommandInteraction,
+) {
+    let pattern = get_option(&interaction.data, "memeid");
+    let pattern = pattern.as_ref().and_then(|p| p.as_str());
+    let page = get_option(&interaction.data, "page")
+        .and_then(|p| p.as_u64())
+        .unwrap_or(1)
+        .max(1) as usize;
+
+    // collect the matching memes, sorted
+    let mut memeids: Vec<&String> = frepo
+        .formats
+        .keys()
+        .filter(|memeid| pattern.is_none_or(|p| wildcard_match(p, memeid)))
+        .collect();
+    memeids.sort();
+
+    // split them into pages which fit in a message
+    let mut pages: Vec<String> = vec![String::new()];
+    for memeid in memeids {
+        if pages.last().unwrap().len() + memeid.len() + 1 > LISTMEMES_PAGE_CHARS {
+            pages.push(String::new());
+        }
+        pages.last_mut().unwrap().push_str(&format!("{}\n", memeid));
+    }
+
+    // start writing message
+    let mut mb = MessageBuilder::new();
+    let matching = match pattern {
+        Some(p) if p.chars().count() > LISTMEMES_ECHOED_PATTERN_CHARS => format!(
+            " matching `{}...`",
+            p.chars()
+                .take(LISTMEMES_ECHOED_PATTERN_CHARS)
+                .collect::<String>()
+        ),
+        Some(p) => format!(" matching `{}`", p),
+        None => String::new(),
+    };
+    if pages[0].is_empty() {
+        mb.push(format!(":mag: No memes found{}.", matching));
+    } else if page > pages.len() {
+        mb.push(format!(
+            ":bangbang: There are only {} pages of memes{}.",
+            pages.len(),
+            matching
+        ));
+    } else {
+        mb.push_underline(format!(
+            "Listing available memes{} (page {} of {})...\n",
+            matching,
+            page,
+            pages.len()
+        ));
+        mb.push(&pages[page - 1]);
+        if page < pages.len() {
+            mb.push_italic(format!("Use page:{} to see more.", page + 1));
+        }
+    }
+    // output the message as response
+    let output = mb.build();
     ctx.http
         .create_interaction_response(
             *interaction.id.as_u64(),
             &interaction.token,
-            &json!({"type": 4, "data": { "content": resp }}),
+            &json!({
+                "type": 4,
+                "data": {
+                    "content": output
+                }
+            }),
         )
         .await
         .unwrap();
 }
 
-async fn respond_listmemes(frepo: &FormatRepo, ctx: Context, interaction: Interaction) {
-    // start writing message
+async fn respond_searchmemes(
+    frepo: &FormatRepo,
+    ctx: Context,
+    interaction: ApplicationCommandInteraction,
+) {
+    let query = interaction
+        .data
+        .options
+        .clone()
+        .into_iter()
+        .find(|o| o.name.eq("query"))
+        .expect("No query interaction argument found")
+        .value
+        .expect("Query had no value");
+    let query = query.as_str().expect("Query wasn't a string");
+
+    // run the search and list the results with their inserts
+    let results = frepo.search(query, 10);
     let mut mb = MessageBuilder::new();
-    mb.push_underline("Listing available memes...\n");
-    // print all memes into message
-    let mut memeids: Vec<&String> = frepo.formats.keys().collect();
-    memeids.sort();
-    for memeid in memeids {
-        mb.push(format!("{}\n", memeid));
+    if results.is_empty() {
+        mb.push(":mag: No memes found matching ")
+            .push_mono_safe(query)
+            .push(".");
+    } else {
+        mb.push_underline("Memes matching")
+            .push(" ")
+            .push_mono_safe(query)
+            .push_line("...");
+        for format in results {
+            let mut insert_names: Vec<&String> = format.inserts.keys().collect();
+            insert_names.sort();
+            mb.push_mono(&format.memeid);
+            if let Some(description) = &format.description {
+                mb.push(" - ").push_safe(description);
+            }
+            if !insert_names.is_empty() {
+                mb.push(format!(
+                    " (inserts: {})",
+                    insert_names
+                        .iter()
+                        .map(|s| format!("`{}`", s))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ));
+            }
+            mb.push("\n");
+        }
     }
-    // output the message as response
     let mut output = mb.build();
-    if output.len() > 1990 {
-        output.truncate(1990);
-        output = format!("{} ...", output);
-    }
+    truncate_reply(&mut output, " ...");
     ctx.http
         .create_interaction_response(
             *interaction.id.as_u64(),
             &interaction.token,
-            &json!({
-                "type": 4,
-                "data": {
-                    "content": output
+            &json!({"type": 4, "data": { "content": output }}),
+        )
+        .await
+        .unwrap();
+}
+
+/// Cut a reply which is over Discord's 2000 character limit down to fit,
+/// at a char boundary, and end it with `more`.
+fn truncate_reply(reply: &mut String, more: &str) {
+    if reply.len() > 2000 {
+        let cut = (0..=2000 - more.len())
+            .rev()
+            .find(|i| reply.is_char_boundary(*i))
+            .unwrap();
+        reply.truncate(cut);
+        reply.push_str(more);
+    }
+}
+
+/// Reload the format repo from disk, for admins only.
+async fn respond_reloadmemes(
+    repo: &SharedRepo,
+    settings: &HashMap<String, String>,
+    ctx: Context,
+    interaction: ApplicationCommandInteraction,
+) {
+    let user_id = interaction.user.id.0;
+    let is_admin = match settings.get("admin_user_ids") {
+        Some(ids) => ids.split(',').any(|id| id.trim() == user_id.to_string()),
+        None => false,
+    };
+
+    let resp = if !is_admin {
+        String::from(":no_entry: Only bot admins can reload the meme repository.")
+    } else {
+        match repo.reload().await {
+            Ok(changes) => {
+                let mut resp = MessageBuilder::new();
+                resp.push_line(format!(
+                    ":white_check_mark: Reloaded, now serving {} memes.",
+                    repo.get().formats.len()
+                ));
+                for (heading, memeids) in
+                    &[("Added", &changes.added), ("Removed", &changes.removed)]
+                {
+                    if !memeids.is_empty() {
+                        resp.push_bold(format!("{}: ", heading))
+                            .push_line(memeids.join(", "));
+                    }
                 }
-            }),
+                if !changes.skipped.is_empty() {
+                    resp.push_bold_line("Skipped:");
+                    for diagnostic in &changes.skipped {
+                        resp.push_line(format!("- {}", diagnostic));
+                    }
+                }
+                let mut resp = resp.build();
+                truncate_reply(&mut resp, "\n...");
+                resp
+            }
+            Err(e) => format!(
+                ":bangbang: __Reload failed:__ {}\nStill serving the old repository.",
+                e
+            ),
+        }
+    };
+    println!("{}", resp);
+    ctx.http
+        .create_interaction_response(
+            *interaction.id.as_u64(),
+            &interaction.token,
+            &json!({"type": 4, "data": { "content": resp, "flags": 64 }}),
         )
         .await
         .unwrap();
 }
 
-async fn respond_memeinfo(frepo: &FormatRepo, ctx: Context, interaction: Interaction) {
+/// Discord forms hold at most this many fields.
+const MAX_FORM_FIELDS: usize = 5;
+
+/// Ask for the text of a meme in a form, with a field for each insert of the
+/// format and, if there's room, its top and bottom captions.
+async fn respond_make(
+    frepo: &FormatRepo,
+    ctx: Context,
+    interaction: ApplicationCommandInteraction,
+) {
+    let format = get_option(&interaction.data, "format")
+        .and_then(|v| v.as_str().map(String::from))
+        .expect("No format interaction argument found");
+    let fields = frepo
+        .resolve(&format)
+        .map_err(|e| format!(":bangbang: {}", e))
+        .and_then(|format| Ok((format, make_form_fields(format)?)));
+    let (format, fields) = match fields {
+        Ok(v) => v,
+        Err(e) => {
+            interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.interaction_response_data(|d| d.content(e))
+                })
+                .await
+                .unwrap();
+            return;
+        }
+    };
+
+    interaction
+        .create_interaction_response(&ctx.http, |r| {
+            r.kind(InteractionResponseType::Modal)
+                .interaction_response_data(|d| {
+                    d.custom_id(format!("make:{}", format.memeid))
+                        .title(shorten(&format!("Make {}", format.memeid), 45))
+                        .components(|c| {
+                            for (id, label) in &fields {
+                                c.create_action_row(|row| {
+                                    row.create_input_text(|t| {
+                                        t.custom_id(id)
+                                            .label(shorten(label, 45))
+                                            .style(InputTextStyle::Short)
+                                            .required(false)
+                                    })
+                                });
+                            }
+                            c
+                        })
+                })
+        })
+        .await
+        .unwrap();
+}
+
+/// The custom ID and label of each field of the /make form for `format`.
+/// Inserts come first, then the captions while there's room.
+fn make_form_fields(format: &MemeFormat) -> Result<Vec<(String, String)>, String> {
+    let mut insert_names: Vec<&String> = format.inserts.keys().collect();
+    insert_names.sort();
+    if insert_names.len() > MAX_FORM_FIELDS {
+        return Err(format!(
+            ":bangbang: `{}` has {} inserts, but Discord forms hold at most {} fields. \
+            Write its MDL instead, `/memeinfo` has an example.",
+            format.memeid,
+            insert_names.len(),
+            MAX_FORM_FIELDS
+        ));
+    }
+    let mut fields: Vec<(String, String)> = insert_names
+        .into_iter()
+        .map(|name| (format!("insert:{}", name), name.clone()))
+        .collect();
+    for (key, label) in &[("topText", "Top text"), ("bottomText", "Bottom text")] {
+        if fields.len() < MAX_FORM_FIELDS {
+            fields.push((format!("caption:{}", key), label.to_string()));
+        }
+    }
+    Ok(fields)
+}
+
+/// `s`, cut to at most `max` characters.
+fn shorten(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+/// Make the meme from a filled in /make form. Reply with its MDL, so people
+/// can learn it, then post the meme.
+async fn respond_make_form(frepo: &FormatRepo, ctx: Context, interaction: ModalSubmitInteraction) {
+    let format = &interaction.data.custom_id["make:".len()..];
+    let fields: Vec<(String, String)> = interaction
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .filter_map(|component| match component {
+            ActionRowComponent::InputText(input) => {
+                Some((input.custom_id.clone(), input.value.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+
+    // write out the MDL for the form, then treat it like any other MDL
+    let meme_image = make_mdl(frepo, format, &fields).and_then(|mdl| {
+        let meme = mdl_chef::parse_mdl(&mdl)?;
+        let meme_image = mdl_chef::render_mdl(&meme, frepo, &[])?;
+        Ok((mdl, meme_image))
+    });
+    let (mdl, meme_image) = match meme_image {
+        Ok(v) => v,
+        Err(e) => {
+            reply_mdl_error(&ctx, interaction.id, &interaction.token, &e).await;
+            return;
+        }
+    };
+
+    interaction
+        .create_interaction_response(&ctx.http, |r| {
+            r.interaction_response_data(|d| {
+                d.content(format!(
+                    "Here's your meme! In MDL, it looks like this:\n```js\n{}\n```",
+                    mdl
+                ))
+            })
+        })
+        .await
+        .unwrap();
+    if let Err(why) = interaction
+        .channel_id
+        .send_message(&ctx, |m| {
+            m.add_file(AttachmentType::Bytes {
+                data: std::borrow::Cow::from(meme_image.data),
+                filename: format!("meme.{}", meme_image.extension),
+            })
+        })
+        .await
+    {
+        println!("Error sending message: {:?}", why);
+    }
+}
+
+/// Build the MDL equivalent to a filled in /make form. `fields` are the
+/// custom IDs and values of its fields, see `make_form_fields`. Empty
+/// fields are left out.
+fn make_mdl(
+    frepo: &FormatRepo,
+    format: &str,
+    fields: &[(String, String)],
+) -> Result<String, MdlError> {
+    let format = frepo.resolve(format)?;
+    // JSON string escaping is valid JSON5, and insert names might not be
+    // identifiers, so they're quoted like the text
+    let quote = |s: &str| serde_json::to_string(s).unwrap();
+    let mut captions: Vec<(String, &str)> = Vec::new();
+    let mut inserts: Vec<(String, &str)> = Vec::new();
+    for (id, text) in fields {
+        let text = text.trim();
+        if text.is_empty() {
+            continue;
+        }
+        let (pairs, key) = match id.split_once(':') {
+            Some(("caption", key)) if key == "topText" || key == "bottomText" => {
+                (&mut captions, key.to_string())
+            }
+            Some(("insert", name)) if format.inserts.contains_key(name) => {
+                (&mut inserts, quote(name))
+            }
+            Some(("insert", name)) => {
+                return Err(MdlError::UnknownInsert {
+                    format: format.memeid.clone(),
+                    insert: name.to_string(),
+                    suggestions: suggest(name, format.inserts.keys(), MAX_SUGGESTIONS),
+                })
+            }
+            _ => {
+                return Err(MdlError::ParseError {
+                    message: format!("The form had an unexpected field `{}`.", id),
+                    line: None,
+                    column: None,
+                })
+            }
+        };
+        if pairs.iter().any(|(k, _)| *k == key) {
+            return Err(MdlError::ParseError {
+                message: format!("The form filled in `{}` twice.", id),
+                line: None,
+                column: None,
+            });
+        }
+        pairs.push((key, text));
+    }
+
+    let object = |name: &str, pairs: &[(String, &str)]| {
+        let pairs: Vec<String> = pairs
+            .iter()
+            .map(|(key, text)| format!("    {}: {}", key, quote(text)))
+            .collect();
+        format!(",\n  {}: {{\n{}\n  }}", name, pairs.join(",\n"))
+    };
+    let mut mdl = format!(
+        "{{\n  version: \"MDL/1.1\",\n  type: \"meme\",\n  base: {}",
+        quote(&format.memeid)
+    );
+    if !captions.is_empty() {
+        mdl.push_str(&object("caption", &captions));
+    }
+    if !inserts.is_empty() {
+        mdl.push_str(&object("inserts", &inserts));
+    }
+    mdl.push_str("\n}");
+    Ok(mdl)
+}
+
+async fn respond_memeinfo(
+    frepo: &FormatRepo,
+    ctx: Context,
+    interaction: ApplicationCommandInteraction,
+) {
     let memeid = interaction
-        .clone()
         .data
-        .unwrap()
         .options
+        .clone()
         .into_iter()
         .find(|o| o.name.eq("memeid"))
         .expect("No memeid interaction argument found")
@@ -159,101 +640,378 @@ async fn respond_memeinfo(frepo: &FormatRepo, ctx: Context, interaction: Interac
         .expect("Memeid had no value");
     let memeid = memeid.as_str().expect("Memeid wasn't a string");
 
-    // check if it's a valid memeid
-    if let Some(format) = frepo.formats.get(memeid) {
-        // it is valid!
-        let insert_names = format
-            .inserts
-            .keys()
-            .map(|s| s.to_string())
-            .collect::<Vec<String>>();
-        // construct the example MDL
-        let mut inserts_mdl = String::new();
-        if insert_names.len() > 0 {
-            inserts_mdl.push_str(",\n  inserts: {\n");
-            for (i, insert) in insert_names.iter().enumerate() {
-                inserts_mdl.push_str(
-                    format!(
-                        "    {}: \"{}\"{}\n",
-                        insert,
-                        insert,
-                        if i == insert_names.len() - 1 { "" } else { "," }
-                    )
-                    .as_str(),
-                );
-            }
-            inserts_mdl.push_str("  }");
+    // check if it's a valid memeid, or something which resolves to one
+    match frepo.resolve(memeid) {
+        Ok(format) => {
+            // it is valid!
+            let insert_names = format
+                .inserts
+                .keys()
+                .map(|s| s.to_string())
+                .collect::<Vec<String>>();
+            let example_mdl = example_mdl(&format.memeid, &insert_names);
+            // generate example meme from the example mdl. Templates can
+            // still fail to render, e.g. with a font which isn't installed
+            let meme_image = mdl_chef::parse_mdl(&example_mdl)
+                .and_then(|meme| mdl_chef::render_mdl(&meme, frepo, &[]));
+            let meme_image = match meme_image {
+                Ok(v) => v,
+                Err(e) => {
+                    reply_mdl_error(&ctx, interaction.id, &interaction.token, &e).await;
+                    return;
+                }
+            };
+            // send a temporary message with the example meme
+            let sent_with_attachment = interaction
+                .channel_id
+                .send_message(&ctx, |m| {
+                    m.add_file(AttachmentType::Bytes {
+                        data: std::borrow::Cow::from(meme_image.data),
+                        filename: format!("meme.{}", meme_image.extension),
+                    })
+                })
+                .await
+                .unwrap();
+            // get the url of the image we just uploaded
+            let attachment_url = &sent_with_attachment.attachments[0].proxy_url;
+            // send message with a proper embed
+            interaction
+                .create_interaction_response(&ctx.http, |r| {
+                    r.interaction_response_data(|d| {
+                        d.embed(|e| {
+                            e.title(&format.memeid);
+                            e.description("MDLChef Meme Information");
+                            e.color(serenity::utils::Colour::from_rgb(133, 198, 232));
+                            if let Some(name) = &format.name {
+                                e.field("Name", name, false);
+                            }
+                            if let Some(description) = &format.description {
+                                e.field("Description", description, false);
+                            }
+                            e.field("Filename", format.image_path.to_str().unwrap(), false);
+                            if !format.aliases.is_empty() {
+                                e.field("Aliases", format.aliases.join(", "), false);
+                            }
+                            if !format.tags.is_empty() {
+                                e.field("Tags", format.tags.join(", "), false);
+                            }
+                            let credit: Vec<String> = vec![
+                                format.author.as_ref().map(|a| format!("By {}", a)),
+                                format.source.clone(),
+                                format.license.as_ref().map(|l| format!("License: {}", l)),
+                            ]
+                            .into_iter()
+                            .flatten()
+                            .collect();
+                            if !credit.is_empty() {
+                                e.field("Credit", credit.join("\n"), false);
+                            }
+                            e.field(
+                                "Inserts",
+                                if !insert_names.is_empty() {
+                                    insert_names
+                                        .iter()
+                                        .map(|s| format!("`{}`", s))
+                                        .collect::<Vec<String>>()
+                                        .join(", ")
+                                } else {
+                                    "*None*".to_string()
+                                },
+                                false,
+                            );
+                            e.field("Example MDL", format!("```js\n{}\n```", example_mdl), false);
+                            e.thumbnail(attachment_url);
+                            e
+                        })
+                    })
+                })
+                .await
+                .unwrap();
+            // delete the temporary message
+            sent_with_attachment.delete(&ctx).await.unwrap();
+        }
+        Err(e) => {
+            // invalid meme id
+            interaction
+                .create_interaction_response(ctx.http, |r| {
+                    r.interaction_response_data(|d| d.content(format!(":bangbang: {}", e)))
+                })
+                .await
+                .unwrap();
+        }
+    };
+}
+
+/// MDL for `memeid` with empty captions, and each insert filled in with its
+/// name, to show in /memeinfo.
+fn example_mdl(memeid: &str, insert_names: &[String]) -> String {
+    // JSON string escaping is valid JSON5, and insert names might not be
+    // identifiers
+    let quote = |s: &str| serde_json::to_string(s).unwrap();
+    let mut inserts_mdl = String::new();
+    if !insert_names.is_empty() {
+        inserts_mdl.push_str(",\n  inserts: {\n");
+        for (i, insert) in insert_names.iter().enumerate() {
+            inserts_mdl.push_str(
+                format!(
+                    "    {}: {}{}\n",
+                    quote(insert),
+                    quote(insert),
+                    if i == insert_names.len() - 1 { "" } else { "," }
+                )
+                .as_str(),
+            );
         }
-        let example_mdl = format!(
-            r#"{{
+        inserts_mdl.push_str("  }");
+    }
+    format!(
+        r#"{{
   version: "MDL/1.1",
   type: "meme",
-  base: "{}",
+  base: {},
   caption: {{
     topText: "",
     bottomText: ""
   }}{}
 }}"#,
-            format.memeid, inserts_mdl
+        quote(memeid),
+        inserts_mdl
+    )
+}
+
+/// Reply to an interaction with an MDL error.
+async fn reply_mdl_error(ctx: &Context, id: InteractionId, token: &str, e: &MdlError) {
+    println!(
+        "Replying with error! {} {}",
+        e.title().red().bold(),
+        e.to_string().red()
+    );
+    let content = format!(":bangbang: __{}:__ {}", e.title(), e);
+    if let Err(why) = ctx
+        .http
+        .create_interaction_response(
+            id.0,
+            token,
+            &json!({"type": 4, "data": { "content": content }}),
+        )
+        .await
+    {
+        println!("Error sending message: {:?}", why);
+    }
+}
+
+/// Get the value of an option passed to the slash command, if it was given.
+fn get_option(data: &CommandData, name: &str) -> Option<serde_json::Value> {
+    data.options.iter().find(|o| o.name.eq(name))?.value.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdl_chef::mdl::{MdlDocument, MdlInsert};
+
+    fn parse_options(json: serde_json::Value) -> Vec<CommandDataOption> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn focused_option_is_the_one_discord_marks() {
+        let options = parse_options(json!([
+            { "name": "format", "type": 3, "value": "drake" },
+            { "name": "inserts", "type": 3, "value": "bad=x; go", "focused": true },
+        ]));
+        assert_eq!(focused_option(&options), Some(("inserts", "bad=x; go")));
+
+        let options = parse_options(json!([
+            { "name": "format", "type": 3, "value": "dra", "focused": true },
+            { "name": "inserts", "type": 3, "value": "bad=x" },
+        ]));
+        assert_eq!(focused_option(&options), Some(("format", "dra")));
+    }
+
+    #[test]
+    fn focused_option_can_be_empty() {
+        let options = parse_options(json!([
+            { "name": "format", "type": 3, "value": "drake" },
+            { "name": "inserts", "type": 3, "value": "", "focused": true },
+        ]));
+        assert_eq!(focused_option(&options), Some(("inserts", "")));
+    }
+
+    #[test]
+    fn no_focused_option_without_the_flag() {
+        let options = parse_options(json!([
+            { "name": "format", "type": 3, "value": "drake" },
+        ]));
+        assert_eq!(focused_option(&options), None);
+        assert_eq!(focused_option(&[]), None);
+    }
+
+    fn repo() -> FormatRepo {
+        FormatRepo::new(std::path::PathBuf::from("memeformats"), "Meme".to_string()).unwrap()
+    }
+
+    fn fields(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(id, text)| (id.to_string(), text.to_string()))
+            .collect()
+    }
+
+    /// The text of each insert of the meme `make_mdl` writes.
+    fn made_inserts(mdl: &str) -> Vec<(String, String)> {
+        let meme = match mdl_chef::parse_mdl(mdl).unwrap() {
+            MdlDocument::Meme(meme) => meme,
+            other => panic!("not a meme: {:?}", other),
+        };
+        meme.inserts
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, insert)| match insert {
+                MdlInsert::Text(text) => (name, text.text),
+                MdlInsert::Image(image) => panic!("not text: {:?}", image),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn form_has_inserts_then_captions() {
+        let repo = repo();
+        let drake = repo.resolve("drake").unwrap();
+        assert_eq!(
+            make_form_fields(drake).unwrap(),
+            fields(&[
+                ("insert:bad", "bad"),
+                ("insert:good", "good"),
+                ("caption:topText", "Top text"),
+                ("caption:bottomText", "Bottom text"),
+            ])
         );
-        // generate example meme from the example mdl
-        let example_meme: MdlMeme = json5::from_str(&example_mdl).unwrap();
-        let meme_image = crate::meme_generator::mdl_to_meme(&example_meme, frepo).unwrap();
-        // send a temporary message with the example meme
-        let sent_with_attachment = interaction
-            .channel_id
+    }
+
+    #[test]
+    fn captions_are_left_out_of_full_forms() {
+        let mut repo = repo();
+        let format = repo.formats.get_mut("Meme.DrakeYesNo").unwrap();
+        for name in &["c", "d"] {
+            format.inserts.insert(name.to_string(), ((0, 0), (1, 1)));
+        }
+        let ids: Vec<String> = make_form_fields(format)
             .unwrap()
-            .send_message(&ctx, |m| {
-                m.add_file(serenity::http::AttachmentType::Bytes {
-                    data: std::borrow::Cow::from(meme_image),
-                    filename: String::from("meme.png"),
-                })
-            })
-            .await
-            .unwrap();
-        // get the url of the image we just uploaded
-        let attachment_url = &sent_with_attachment.attachments[0].proxy_url;
-        // send message with a proper embed
-        interaction
-            .create_interaction_response(&ctx.http, |r| {
-                r.interaction_response_data(|d| {
-                    d.embed(|e| {
-                        e.title(&format.memeid);
-                        e.description("MDLChef Meme Information");
-                        e.color(serenity::utils::Colour::from_rgb(133, 198, 232));
-                        e.field("Filename", &format.image_path.to_str().unwrap(), false);
-                        e.field(
-                            "Inserts",
-                            if insert_names.len() > 0 {
-                                insert_names
-                                    .iter()
-                                    .map(|s| format!("`{}`", s))
-                                    .collect::<Vec<String>>()
-                                    .join(", ")
-                            } else {
-                                "*None*".to_string()
-                            },
-                            false,
-                        );
-                        e.field("Example MDL", format!("```js\n{}\n```", example_mdl), false);
-                        e.thumbnail(attachment_url);
-                        e
-                    })
-                })
-            })
-            .await
-            .unwrap();
-        // delete the temporary message
-        sent_with_attachment.delete(&ctx).await.unwrap();
-    } else {
-        // invalid meme id
-        interaction
-            .create_interaction_response(ctx.http, |r| {
-                r.interaction_response_data(|d| {
-                    d.content(format!(":bangbang: Invalid meme identifier `{}`.", memeid))
-                })
-            })
-            .await
-            .unwrap();
-    };
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                "insert:bad",
+                "insert:c",
+                "insert:d",
+                "insert:good",
+                "caption:topText"
+            ]
+        );
+
+        for name in &["e", "f"] {
+            format.inserts.insert(name.to_string(), ((0, 0), (1, 1)));
+        }
+        assert!(make_form_fields(format).is_err());
+    }
+
+    #[test]
+    fn form_text_is_kept_as_typed() {
+        let repo = repo();
+        let text = r#"a; b=c \ "quoted" \"#;
+        let mdl = make_mdl(
+            &repo,
+            "drake",
+            &fields(&[("insert:bad", text), ("insert:good", "  ; = ")]),
+        )
+        .unwrap();
+        assert_eq!(
+            made_inserts(&mdl),
+            vec![
+                ("bad".to_string(), text.trim().to_string()),
+                ("good".to_string(), "; =".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_form_fields_are_left_out() {
+        let repo = repo();
+        let mdl = make_mdl(
+            &repo,
+            "drake",
+            &fields(&[
+                ("insert:bad", ""),
+                ("insert:good", "  "),
+                ("caption:topText", "top"),
+                ("caption:bottomText", ""),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            mdl,
+            "{\n  version: \"MDL/1.1\",\n  type: \"meme\",\n  base: \"Meme.DrakeYesNo\",\n  \
+            caption: {\n    topText: \"top\"\n  }\n}"
+        );
+        assert!(made_inserts(&mdl).is_empty());
+    }
+
+    #[test]
+    fn insert_names_are_quoted() {
+        let mut repo = repo();
+        let drake = repo.formats.get_mut("Meme.DrakeYesNo").unwrap();
+        drake
+            .inserts
+            .insert("my \"insert\"".to_string(), ((0, 0), (1, 1)));
+        let mdl = make_mdl(&repo, "drake", &fields(&[("insert:my \"insert\"", "x")])).unwrap();
+        assert_eq!(
+            made_inserts(&mdl),
+            vec![("my \"insert\"".to_string(), "x".to_string())]
+        );
+    }
+
+    #[test]
+    fn bad_form_fields_are_rejected() {
+        let repo = repo();
+        let make = |f: &[(&str, &str)]| make_mdl(&repo, "drake", &fields(f));
+        assert!(matches!(
+            make(&[("insert:gud", "x")]),
+            Err(MdlError::UnknownInsert { .. })
+        ));
+        assert!(matches!(
+            make(&[("insert:bad", "x"), ("insert:bad", "y")]),
+            Err(MdlError::ParseError { .. })
+        ));
+        assert!(matches!(
+            make(&[("caption:centerText", "x")]),
+            Err(MdlError::ParseError { .. })
+        ));
+        assert!(matches!(
+            make(&[("bad", "x")]),
+            Err(MdlError::ParseError { .. })
+        ));
+        assert!(make(&[("insert:bad", "x"), ("insert:bad", "")]).is_ok());
+    }
+
+    #[test]
+    fn example_mdl_quotes_insert_names() {
+        let names = vec!["bad".to_string(), "my \"odd\" insert".to_string()];
+        let mdl = example_mdl("Meme.DrakeYesNo", &names);
+        let mut inserts = made_inserts(&mdl);
+        inserts.sort();
+        assert_eq!(
+            inserts,
+            vec![
+                ("bad".to_string(), "bad".to_string()),
+                (
+                    "my \"odd\" insert".to_string(),
+                    "my \"odd\" insert".to_string()
+                )
+            ]
+        );
+        assert!(mdl_chef::parse_mdl(&example_mdl("Meme.UtopianWorld", &[])).is_ok());
+    }
 }
@@ -1,13 +1,12 @@
 use serenity::{model::channel::Message, prelude::*};
 
 use colored::*;
-use json5;
 
-use crate::{mdl::MdlMeme, meme_generator};
+use mdl_chef::{attachments, mdl::MdlDocument, meme_generator, MdlError};
 
 /// Call this to respond to a message containing suspected MDL JSON.
 pub async fn respond_mdl(
-    frepo: &super::meme_repository::FormatRepo,
+    frepo: &mdl_chef::meme_repository::FormatRepo,
     ctx: Context,
     msg: &Message,
     mdlstr: &str
@@ -19,57 +18,51 @@ pub async fn respond_mdl(
         msg.author.name.yellow()
     );
 
-    // Attempt deserialization
-    let meme: MdlMeme = match json5::from_str(mdlstr) {
+    // Attempt deserialization and validation
+    let meme: MdlDocument = match mdl_chef::parse_mdl(mdlstr) {
         Ok(v) => v,
         Err(e) => {
-            reply_error(ctx, msg, "MDL Parsing Failure", &e.to_string(), true).await;
+            reply_mdl_error(ctx, msg, &e).await;
             return;
         }
     };
 
-    // VALIDATION
-    if meme.r#type != "meme" {
-        reply_error(
-            ctx,
-            msg,
-            "MDL Validation Failure",
-            "`type` field did not equal 'meme'.",
-            false,
-        )
-        .await;
-        return;
-    }
-    if meme.version != "MDL/1.1" {
-        reply_error(
-            ctx,
-            msg,
-            "MDL Validation Failure",
-            "`version` field did not equal 'MDL/1.1'. This is the only supported \
-            version as of now.",
-            false,
-        )
-        .await;
-        return;
-    }
-
     // Appears to be a valid MDL meme
     println!("{}", "Looks valid!".green());
     //println!("{:#?}", meme);
 
+    // Download the attachments the MDL refers to. The others stay empty,
+    // so the ones we have keep their index.
+    let mut attachments = vec![Vec::new(); msg.attachments.len()];
+    for index in meme.attachment_indexes() {
+        // a missing one is reported by the generator
+        let attachment = match msg.attachments.get(index) {
+            Some(attachment) => attachment,
+            None => continue,
+        };
+        // don't download what we'd refuse anyway
+        if let Err(problem) = attachments::check_size(attachment.size) {
+            reply_mdl_error(ctx, msg, &MdlError::BadAttachment { index, problem }).await;
+            return;
+        }
+        match attachment.download().await {
+            Ok(data) => attachments[index] = data,
+            Err(why) => {
+                let error = format!("Could not download {}: {}", attachment.filename, why);
+                reply_error(ctx, msg, "Attachment Failure", &error, false).await;
+                return;
+            }
+        }
+    }
+
     // Generate the meme and handle errors
-    let mut failflag = String::new();
-    let memegen_result = match meme_generator::mdl_to_meme(&meme, frepo) {
+    let memegen_result = match meme_generator::render_mdl(&meme, frepo, &attachments) {
         Ok(v) => v,
         Err(e) => {
-            failflag = format!("{}", e);
-            Vec::new()
+            reply_mdl_error(ctx, msg, &e).await;
+            return;
         }
     };
-    if failflag != "" {
-        reply_error(ctx, msg, "Meme Generation Failure", &failflag, true).await;
-        return;
-    }
 
     // Reply with attachment
     if let Err(why) = msg
@@ -78,9 +71,9 @@ pub async fn respond_mdl(
             if msg.content.contains("#verbose") {
                 m.content(format!("```js\n// MDLChef MDIR\n{:#?}\n```", meme));
             }
-            m.add_file(serenity::http::AttachmentType::Bytes {
-                data: std::borrow::Cow::from(memegen_result),
-                filename: String::from("meme.png"),
+            m.add_file(serenity::model::channel::AttachmentType::Bytes {
+                data: std::borrow::Cow::from(memegen_result.data),
+                filename: format!("meme.{}", memegen_result.extension),
             });
             m.reference_message(msg);
             m
@@ -91,6 +84,18 @@ pub async fn respond_mdl(
     };
 }
 
+/// Reply with an MDL error. Short validation complaints are sent inline,
+/// everything else in a code block.
+pub async fn reply_mdl_error(ctx: Context, msg: &Message, e: &MdlError) {
+    let code = !matches!(
+        e,
+        MdlError::UnsupportedVersion { .. }
+            | MdlError::BadType { .. }
+            | MdlError::BadComposite { .. }
+    );
+    reply_error(ctx, msg, e.title(), &e.to_string(), code).await;
+}
+
 pub async fn reply_error(ctx: Context, msg: &Message, title: &str, error: &str, code: bool) {
     let fulltext = if code {
         format!(":warning: __{}:__\n```\n{}\n```", title, error)
@@ -0,0 +1,210 @@
+use crate::meme_repository::MemeFormat;
+use crate::suggest::match_score;
+
+// How much a term is worth, by where it came from.
+const WEIGHT_NAME: u32 = 10; // memeID segment or alias
+const WEIGHT_TAG: u32 = 8;
+const WEIGHT_KEYWORD: u32 = 6;
+const WEIGHT_NAME_WORD: u32 = 5; // word within a CamelCase memeID segment
+const WEIGHT_DESCRIPTION: u32 = 3;
+
+/// Searchable terms for every format, built once when the repo loads.
+pub struct SearchIndex {
+    /// memeID and its weighted, lowercase terms
+    entries: Vec<(String, Vec<(String, u32)>)>,
+}
+
+impl SearchIndex {
+    pub fn build<'a>(formats: impl Iterator<Item = &'a MemeFormat>) -> SearchIndex {
+        let entries = formats
+            .map(|f| {
+                let mut terms = Vec::new();
+                for segment in f.memeid.split('.').skip(1) {
+                    terms.push((segment.to_lowercase(), WEIGHT_NAME));
+                    for word in split_camel_case(segment) {
+                        terms.push((word, WEIGHT_NAME_WORD));
+                    }
+                }
+                terms.extend(f.aliases.iter().map(|a| (a.to_lowercase(), WEIGHT_NAME)));
+                terms.extend(f.tags.iter().map(|t| (t.to_lowercase(), WEIGHT_TAG)));
+                terms.extend(
+                    f.keywords
+                        .iter()
+                        .map(|k| (k.to_lowercase(), WEIGHT_KEYWORD)),
+                );
+                if let Some(description) = &f.description {
+                    terms.extend(
+                        words(description)
+                            .into_iter()
+                            .filter(|w| w.len() > 2)
+                            .map(|w| (w, WEIGHT_DESCRIPTION)),
+                    );
+                }
+                (f.memeid.clone(), terms)
+            })
+            .collect();
+        SearchIndex { entries }
+    }
+
+    /// MemeIDs of the formats best matching `query`, best first.
+    /// Each word of the query scores its best match among a format's terms.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<String> {
+        let query_words = words(query);
+        let mut scored: Vec<(u32, &String)> = self
+            .entries
+            .iter()
+            .map(|(memeid, terms)| {
+                let score = query_words
+                    .iter()
+                    .map(|qw| {
+                        terms
+                            .iter()
+                            .map(|(term, weight)| term_score(qw, term, *weight))
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .sum();
+                (score, memeid)
+            })
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+        scored
+            .into_iter()
+            .take(limit)
+            .map(|(_, memeid)| memeid.clone())
+            .collect()
+    }
+}
+
+/// Score one query word against one term: exact beats partial beats typo.
+fn term_score(query_word: &str, term: &str, weight: u32) -> u32 {
+    if query_word == term {
+        weight * 2
+    } else if query_word.len() >= 3 && term.contains(query_word) {
+        weight
+    } else if query_word.len() >= 4 && match_score(query_word, term).is_some() {
+        weight / 2
+    } else {
+        0
+    }
+}
+
+/// Lowercase alphanumeric words of some text.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// "WhatIfIToldYou" -> ["what", "if", "i", "told", "you"]
+fn split_camel_case(segment: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in segment.chars() {
+        if c.is_uppercase() && !word.is_empty() {
+            words.push(word.to_lowercase());
+            word.clear();
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word.to_lowercase());
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    fn format(memeid: &str, aliases: &[&str], tags: &[&str], description: &str) -> MemeFormat {
+        let strings = |s: &[&str]| s.iter().map(|s| s.to_string()).collect();
+        MemeFormat {
+            memeid: memeid.to_string(),
+            image_path: PathBuf::new(),
+            inserts: HashMap::new(),
+            aliases: strings(aliases),
+            description: Some(description.to_string()).filter(|d| !d.is_empty()),
+            tags: strings(tags),
+            keywords: Vec::new(),
+            name: None,
+            author: None,
+            source: None,
+            license: None,
+            style: Default::default(),
+            insert_styles: HashMap::new(),
+            frames: None,
+        }
+    }
+
+    fn index() -> SearchIndex {
+        let formats = [
+            format(
+                "Meme.DrakeYesNo",
+                &["drake"],
+                &["comparison"],
+                "Drake rejecting one thing, then approving of another.",
+            ),
+            format("Meme.Matrix.WhatIfIToldYou", &["morpheus"], &[], ""),
+            format(
+                "Meme.UtopianWorld",
+                &[],
+                &["comparison"],
+                "Die Welt, wenn …",
+            ),
+        ];
+        SearchIndex::build(formats.iter())
+    }
+
+    #[test]
+    fn camel_case_segments_split_into_words() {
+        assert_eq!(
+            split_camel_case("WhatIfIToldYou"),
+            vec!["what", "if", "i", "told", "you"]
+        );
+        assert_eq!(split_camel_case("drake"), vec!["drake"]);
+        assert!(split_camel_case("").is_empty());
+    }
+
+    #[test]
+    fn words_are_lowercase_and_unicode_aware() {
+        assert_eq!(words("Über-cool, ÉCOLE!"), vec!["über", "cool", "école"]);
+        assert!(words("  ...  ").is_empty());
+    }
+
+    #[test]
+    fn names_outrank_tags_and_descriptions() {
+        let index = index();
+        assert_eq!(index.search("drake", 10), vec!["Meme.DrakeYesNo"]);
+        assert_eq!(
+            index.search("comparison", 10),
+            vec!["Meme.DrakeYesNo", "Meme.UtopianWorld"]
+        );
+        assert_eq!(index.search("told", 10), vec!["Meme.Matrix.WhatIfIToldYou"]);
+        assert_eq!(index.search("approving", 10), vec!["Meme.DrakeYesNo"]);
+    }
+
+    #[test]
+    fn typos_and_partial_words_still_match() {
+        let index = index();
+        assert_eq!(
+            index.search("morpheos", 10),
+            vec!["Meme.Matrix.WhatIfIToldYou"]
+        );
+        assert_eq!(index.search("utopia", 10), vec!["Meme.UtopianWorld"]);
+        assert_eq!(index.search("welt", 10), vec!["Meme.UtopianWorld"]);
+    }
+
+    #[test]
+    fn empty_and_unmatched_queries_find_nothing() {
+        let index = index();
+        assert!(index.search("", 10).is_empty());
+        assert!(index.search("!!!", 10).is_empty());
+        assert!(index.search("zebra", 10).is_empty());
+        assert_eq!(index.search("comparison", 1).len(), 1);
+    }
+}
@@ -0,0 +1,34 @@
+use std::sync::{Arc, RwLock};
+
+use mdl_chef::meme_repository::{FormatRepo, RepoChanges};
+
+/// The format repo, swappable while the bot is running.
+/// Readers get the repo as it was when they asked, a reload never
+/// pulls it out from under a meme being generated.
+pub struct SharedRepo(RwLock<Arc<FormatRepo>>);
+
+impl SharedRepo {
+    pub fn new(frepo: FormatRepo) -> SharedRepo {
+        SharedRepo(RwLock::new(Arc::new(frepo)))
+    }
+
+    /// The current repo.
+    pub fn get(&self) -> Arc<FormatRepo> {
+        self.0.read().unwrap().clone()
+    }
+
+    /// Load the repo again from disk and swap it in. If loading fails, the
+    /// old repo stays in place and the error is returned.
+    pub async fn reload(&self) -> Result<RepoChanges, String> {
+        let old = self.get();
+        let loading = old.clone();
+        let new = match tokio::task::spawn_blocking(move || loading.reload()).await {
+            Ok(Ok(new)) => new,
+            Ok(Err(e)) => return Err(e.to_string()),
+            Err(e) => return Err(format!("Loading the repository crashed: {}", e)),
+        };
+        let changes = new.changes_since(&old);
+        *self.0.write().unwrap() = Arc::new(new);
+        Ok(changes)
+    }
+}
@@ -0,0 +1,132 @@
+use serde::{de, Deserialize, Deserializer};
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// How text should look. Every field is optional, so styles from the
+/// template and the MDL can be layered over the defaults.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CaptionStyle {
+    /// name of the font to use
+    pub font: Option<String>,
+    /// fill color of the letters
+    pub color: Option<Color>,
+    /// color of the border around the letters
+    pub outline: Option<Color>,
+    /// thickness of the border, in pixels
+    pub outline_width: Option<u32>,
+    /// largest font size to use, in pixels. Text still shrinks to fit.
+    #[serde(default, deserialize_with = "font_size")]
+    pub size: Option<f32>,
+    pub align: Option<Align>,
+    pub valign: Option<VAlign>,
+    /// whether to capitalize the text
+    pub uppercase: Option<bool>,
+}
+
+/// Font sizes must be below this many pixels.
+pub const MAX_FONT_SIZE: f32 = 1000.0;
+
+fn font_size<'de, D>(deserializer: D) -> Result<Option<f32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let size = f32::deserialize(deserializer)?;
+    if !size.is_finite() || size <= 0.0 || size >= MAX_FONT_SIZE {
+        return Err(de::Error::custom(format!(
+            "size {} is not between 0 and {}",
+            size, MAX_FONT_SIZE
+        )));
+    }
+    Ok(Some(size))
+}
+
+impl CaptionStyle {
+    /// This style, with anything it leaves unset taken from `base`.
+    pub fn layered_over(&self, base: &CaptionStyle) -> CaptionStyle {
+        CaptionStyle {
+            font: self.font.clone().or_else(|| base.font.clone()),
+            color: self.color.or(base.color),
+            outline: self.outline.or(base.outline),
+            outline_width: self.outline_width.or(base.outline_width),
+            size: self.size.or(base.size),
+            align: self.align.or(base.align),
+            valign: self.valign.or(base.valign),
+            uppercase: self.uppercase.or(base.uppercase),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum VAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+/// An RGBA color, written as `#rgb`, `#rrggbb`, `#rrggbbaa`, or a name
+/// like `white`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(try_from = "String")]
+pub struct Color(pub [u8; 4]);
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let named = match s.to_lowercase().as_str() {
+            "white" => Some([255, 255, 255, 255]),
+            "black" => Some([0, 0, 0, 255]),
+            "red" => Some([255, 0, 0, 255]),
+            "green" => Some([0, 128, 0, 255]),
+            "blue" => Some([0, 0, 255, 255]),
+            "yellow" => Some([255, 255, 0, 255]),
+            "gray" | "grey" => Some([128, 128, 128, 255]),
+            "transparent" => Some([0, 0, 0, 0]),
+            _ => None,
+        };
+        if let Some(rgba) = named {
+            return Ok(Color(rgba));
+        }
+
+        let bad_color = || {
+            format!(
+                "\"{}\" is not a color name, #rgb, #rrggbb, or #rrggbbaa.",
+                s
+            )
+        };
+        let hex = s.strip_prefix('#').ok_or_else(bad_color)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(bad_color());
+        }
+        let digits: Vec<u8> = hex.chars().map(|c| c.to_digit(16).unwrap() as u8).collect();
+        match digits.as_slice() {
+            [r, g, b] => Ok(Color([r * 17, g * 17, b * 17, 255])),
+            [r1, r2, g1, g2, b1, b2] => Ok(Color([r1 * 16 + r2, g1 * 16 + g2, b1 * 16 + b2, 255])),
+            [r1, r2, g1, g2, b1, b2, a1, a2] => Ok(Color([
+                r1 * 16 + r2,
+                g1 * 16 + g2,
+                b1 * 16 + b2,
+                a1 * 16 + a2,
+            ])),
+            _ => Err(bad_color()),
+        }
+    }
+}
+
+impl TryFrom<String> for Color {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
@@ -0,0 +1,235 @@
+/// How many "did you mean" suggestions to put in errors.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// How far off a candidate may be and still be suggested, as a fraction of
+/// the input length. Always allows at least one typo.
+const MAX_DISTANCE_RATIO: f32 = 0.34;
+
+/// Score how well `candidate` matches what the user typed. Lower is better,
+/// `None` means it is not worth suggesting.
+///
+/// Matching is case-insensitive. Identifiers are dotted (`Meme.Matrix.WhatIfIToldYou`),
+/// so the input is compared against both the whole identifier and its trailing
+/// segments, letting `WhatIfIToldYou` or `matrix.whatifitoldyou` find it.
+pub fn match_score(input: &str, candidate: &str) -> Option<usize> {
+    let input = input.to_lowercase();
+    let candidate = candidate.to_lowercase();
+    if input.is_empty() {
+        return None;
+    }
+    if input == candidate {
+        return Some(0);
+    }
+
+    // suffix made of whole segments, e.g. "drakeyesno" for "meme.drakeyesno"
+    let suffixes: Vec<&str> = candidate
+        .match_indices('.')
+        .map(|(i, _)| &candidate[i + 1..])
+        .collect();
+    if suffixes.contains(&input.as_str()) {
+        return Some(1);
+    }
+
+    // otherwise allow a few typos against the whole thing or any suffix
+    let max_distance = std::cmp::max(
+        1,
+        (input.chars().count() as f32 * MAX_DISTANCE_RATIO) as usize,
+    );
+    std::iter::once(candidate.as_str())
+        .chain(suffixes)
+        .map(|c| edit_distance(&input, c))
+        .min()
+        .filter(|d| *d <= max_distance)
+        .map(|d| d + 1)
+}
+
+/// Return up to `limit` of the `candidates` closest to `input`, best first.
+pub fn suggest<'a, I>(input: &str, candidates: I, limit: usize) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let mut scored: Vec<(usize, &String)> = candidates
+        .into_iter()
+        .filter_map(|c| match_score(input, c).map(|s| (s, c)))
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, c)| c.clone())
+        .collect()
+}
+
+/// Return up to `limit` of the `candidates` which `input` could be the start
+/// of, for autocompletion. Prefixes of the identifier or of its trailing
+/// segments rank first, then substrings, then the typo-tolerant matches of
+/// [`match_score`]. Empty input lists candidates alphabetically.
+pub fn complete<'a, I>(input: &str, candidates: I, limit: usize) -> Vec<String>
+where
+    I: IntoIterator<Item = &'a String>,
+{
+    let input_lower = input.to_lowercase();
+    let mut scored: Vec<(usize, &String)> = candidates
+        .into_iter()
+        .filter_map(|c| {
+            let lower = c.to_lowercase();
+            let is_prefix = lower.starts_with(&input_lower)
+                || lower
+                    .match_indices('.')
+                    .any(|(i, _)| lower[i + 1..].starts_with(&input_lower));
+            if is_prefix {
+                Some((0, c))
+            } else if lower.contains(&input_lower) {
+                Some((1, c))
+            } else {
+                match_score(input, c).map(|s| (2 + s, c))
+            }
+        })
+        .collect();
+    scored.sort();
+    scored
+        .into_iter()
+        .take(limit)
+        .map(|(_, c)| c.clone())
+        .collect()
+}
+
+/// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + if ca == *cb { 0 } else { 1 };
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// Case-insensitive match where `*` stands for any run of characters.
+/// A pattern without any `*` matches anywhere in the text.
+pub fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let text = text.to_lowercase();
+    if !pattern.contains('*') {
+        return text.contains(&pattern);
+    }
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !text.starts_with(first) || text.len() < first.len() + last.len() || !text.ends_with(last) {
+        return false;
+    }
+    // the middle parts have to appear in order between the anchored ends
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn exact_and_suffix_matches_score_best() {
+        assert_eq!(match_score("Meme.DrakeYesNo", "Meme.DrakeYesNo"), Some(0));
+        assert_eq!(match_score("meme.drakeyesno", "Meme.DrakeYesNo"), Some(0));
+        assert_eq!(
+            match_score("WhatIfIToldYou", "Meme.Matrix.WhatIfIToldYou"),
+            Some(1)
+        );
+        assert_eq!(
+            match_score("matrix.whatifitoldyou", "Meme.Matrix.WhatIfIToldYou"),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn typos_are_tolerated_up_to_a_limit() {
+        assert_eq!(match_score("DrakYesNo", "Meme.DrakeYesNo"), Some(2));
+        assert_eq!(match_score("Meme.DrakeYesNp", "Meme.DrakeYesNo"), Some(2));
+        assert_eq!(match_score("Pikachu", "Meme.DrakeYesNo"), None);
+    }
+
+    #[test]
+    fn empty_input_matches_nothing() {
+        assert_eq!(match_score("", "Meme.DrakeYesNo"), None);
+        assert!(suggest("", &ids(&["Meme.DrakeYesNo"]), MAX_SUGGESTIONS).is_empty());
+    }
+
+    #[test]
+    fn edit_distance_counts_chars_not_bytes() {
+        assert_eq!(edit_distance("café", "cafe"), 1);
+        assert_eq!(edit_distance("日本語", "日本"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(match_score("ドレイク", "Meme.ドレイク"), Some(1));
+        assert_eq!(match_score("ドレイグ", "Meme.ドレイク"), Some(2));
+        // three typos in four characters is too many, however many bytes
+        assert_eq!(match_score("ドアアア", "Meme.ドレイク"), None);
+    }
+
+    #[test]
+    fn suggestions_are_best_first_and_limited() {
+        let candidates = ids(&[
+            "Meme.UtopianWorld",
+            "Meme.DrakeYesNo",
+            "Meme.Legacy.DrakeYesNo",
+            "Meme.DrakeNoYes",
+        ]);
+        assert_eq!(
+            suggest("drakeyesno", &candidates, MAX_SUGGESTIONS),
+            ids(&["Meme.DrakeYesNo", "Meme.Legacy.DrakeYesNo"])
+        );
+        assert_eq!(
+            suggest("drakeyesno", &candidates, 1),
+            ids(&["Meme.DrakeYesNo"])
+        );
+    }
+
+    #[test]
+    fn wildcards_match_runs_of_characters() {
+        assert!(wildcard_match("Meme.*", "Meme.DrakeYesNo"));
+        assert!(wildcard_match("*.drake*", "Meme.DrakeYesNo"));
+        assert!(wildcard_match("meme.*.*you", "Meme.Matrix.WhatIfIToldYou"));
+        assert!(wildcard_match("*", "Meme.DrakeYesNo"));
+        assert!(wildcard_match("**", ""));
+        assert!(!wildcard_match("Matrix.*", "Meme.Matrix.WhatIfIToldYou"));
+        assert!(!wildcard_match("*.Drake", "Meme.DrakeYesNo"));
+    }
+
+    #[test]
+    fn patterns_without_wildcards_match_anywhere() {
+        assert!(wildcard_match("yesno", "Meme.DrakeYesNo"));
+        assert!(!wildcard_match("noyes", "Meme.DrakeYesNo"));
+        assert!(wildcard_match("", "Meme.DrakeYesNo"));
+        assert!(wildcard_match("", ""));
+    }
+
+    #[test]
+    fn wildcard_ends_do_not_overlap() {
+        assert!(!wildcard_match("ab*ba", "aba"));
+        assert!(wildcard_match("ab*ba", "abba"));
+        assert!(!wildcard_match("a*b*c", "acb"));
+        assert!(wildcard_match("a*b*c", "aXbYc"));
+    }
+
+    #[test]
+    fn wildcards_handle_multibyte_text() {
+        assert!(wildcard_match("Meme.*ク", "Meme.ドレイク"));
+        assert!(wildcard_match("*レ*", "Meme.ドレイク"));
+        assert!(!wildcard_match("ド*ド", "ド"));
+        assert!(wildcard_match("ÜBER*", "über.Meme"));
+        assert!(wildcard_match("*ß", "Meme.Straß"));
+    }
+}